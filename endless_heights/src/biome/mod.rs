@@ -0,0 +1,112 @@
+use crate::World;
+use robotics_lib::world::tile::TileType;
+
+/// Elevation bands used to pick a tile type.
+/// Each threshold is the upper bound of its band, expressed as a fraction between 0 and 1 of the world's elevation range.
+/// Tiles above `mountain` are covered in snow.
+#[derive(Clone, Debug)]
+pub struct BiomeThresholds {
+    pub deep_water: f32,
+    pub shallow_water: f32,
+    pub sand: f32,
+    pub grass: f32,
+    pub hill: f32,
+    pub mountain: f32,
+}
+
+impl Default for BiomeThresholds {
+    fn default() -> Self {
+        Self {
+            deep_water: 0.05,
+            shallow_water: 0.12,
+            sand: 0.18,
+            grass: 0.5,
+            hill: 0.7,
+            mountain: 0.88,
+        }
+    }
+}
+
+impl BiomeThresholds {
+    /// Returns the tile type for an elevation normalized between 0 and 1.
+    pub fn tile_type_for(&self, normalized_elevation: f32) -> TileType {
+        if normalized_elevation <= self.deep_water {
+            TileType::DeepWater
+        } else if normalized_elevation <= self.shallow_water {
+            TileType::ShallowWater
+        } else if normalized_elevation <= self.sand {
+            TileType::Sand
+        } else if normalized_elevation <= self.grass {
+            TileType::Grass
+        } else if normalized_elevation <= self.hill {
+            TileType::Hill
+        } else if normalized_elevation <= self.mountain {
+            TileType::Mountain
+        } else {
+            TileType::Snow
+        }
+    }
+}
+
+/// Returns a function normalizing elevations between 0 and 1 against the lowest and highest tiles of the world.
+/// A flat world has no low nor high ground, so it is normalized to the middle of the `grass` band, where it can be walked on.
+pub(crate) fn normalizer(world: &World, thresholds: &BiomeThresholds) -> impl Fn(usize) -> f32 {
+    let flat = 0.5 * (thresholds.sand + thresholds.grass);
+    let elevations = world.iter().flatten().map(|tile| tile.elevation);
+    let min_elevation = elevations.clone().min().unwrap_or(0);
+    let max_elevation = elevations.max().unwrap_or(0);
+    move |elevation: usize| {
+        if max_elevation == min_elevation {
+            flat
+        } else {
+            elevation.saturating_sub(min_elevation) as f32 / (max_elevation - min_elevation) as f32
+        }
+    }
+}
+
+/// Sets the tile type of every tile of the world from its elevation.
+/// Elevations are normalized between the lowest and the highest tile, so this stage has to run after height::bump_world().
+pub fn classify(world: &mut World, thresholds: &BiomeThresholds) {
+    let normalize = normalizer(world, thresholds);
+    for row in world.iter_mut() {
+        for tile in row.iter_mut() {
+            tile.tile_type = thresholds.tile_type_for(normalize(tile.elevation));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use robotics_lib::world::tile::{Content, Tile};
+
+    fn world_of(elevations: &[usize]) -> World {
+        vec![elevations
+            .iter()
+            .map(|&elevation| Tile {
+                tile_type: TileType::Grass,
+                content: Content::None,
+                elevation,
+            })
+            .collect()]
+    }
+
+    #[test]
+    fn classify_normalizes_from_the_lowest_tile() {
+        let mut world = world_of(&[800, 850, 900, 1000]);
+        classify(&mut world, &BiomeThresholds::default());
+        assert_eq!(world[0][0].tile_type, TileType::DeepWater);
+        assert_eq!(world[0][3].tile_type, TileType::Snow);
+    }
+
+    #[test]
+    fn classify_flat_world_is_grass() {
+        for elevation in [0, 5] {
+            let mut world = world_of(&[elevation; 3]);
+            classify(&mut world, &BiomeThresholds::default());
+            assert!(world[0]
+                .iter()
+                .all(|tile| tile.tile_type == TileType::Grass));
+        }
+    }
+}
@@ -0,0 +1,638 @@
+use std::fmt::Display;
+
+use crate::biome::BiomeThresholds;
+use crate::climate::ClimateParams;
+use crate::connectivity::{ConnectivityMode, ConnectivityParams};
+use crate::content::ContentRule;
+use crate::erosion::ErosionParams;
+use crate::height::kernel::KernelMix;
+use crate::height::ridge::Ridge;
+use crate::height::{BlendMode, HeightMap};
+use crate::hydrology::HydrologyParams;
+use crate::normalize::NormalizeParams;
+use crate::preset::Preset;
+use crate::source::{BlendOp, HeightSource};
+use crate::spawn::SpawnStrategy;
+use crate::utils::Dimension;
+use crate::weather::{Climate, WeatherConfig};
+use crate::WorldGenerator;
+
+/// Errors returned by `WorldGeneratorBuilder::build()` for invalid configurations.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeneratorConfigError {
+    /// The map has no rows or no columns
+    EmptyMap { width: usize, height: usize },
+    /// No gaussians nor mountain ranges would be spawned
+    NoMountains,
+    /// A kernel weight is negative or not finite, or every weight is 0
+    InvalidKernelMix(String),
+    /// A mountain range has no spine points, a point that is not finite, or a width that is not a positive number
+    InvalidRidge(String),
+    /// The scale is not a positive number
+    InvalidScale(f32),
+    /// The prominence of mountains is not above 0 and at most 1
+    InvalidProminence(f32),
+    /// The interpolation is not between 0 and 1
+    InvalidInterpolation(f32),
+    /// The sharpness of BlendMode::SmoothMax is not a positive number
+    InvalidBlendSharpness(f32),
+    /// The minimum variance is not a positive number
+    NonPositiveVariance(f32),
+    /// The minimum variance is not lower than the maximum variance
+    InvalidVarianceRange {
+        min_variance: f32,
+        max_variance: f32,
+    },
+    /// The normalization range is reversed, or its curve is invalid
+    InvalidNormalization(String),
+    /// An erosion pass has no iterations, or a rate out of range
+    InvalidErosion(String),
+    /// The hydrology thresholds would flood every tile
+    InvalidHydrology(String),
+    /// The biome thresholds are not increasing, or not between 0 and 1
+    InvalidBiomeThresholds,
+    /// A content rule has a density outside of 0 and 1, or an empty quantity range
+    InvalidContentRule { index: usize, reason: String },
+    /// The parameters of the climate model are out of range
+    InvalidClimateModel(String),
+    /// The weather configuration is refused by robotics_lib
+    InvalidWeather(String),
+    /// The fixed spawn point lies outside of the map
+    SpawnOutOfBounds { row: usize, col: usize },
+    /// The reachable fraction of the connectivity stage is not between 0 and 1
+    InvalidMinReachable(f32),
+    /// The connectivity stage is set to regenerate with no attempts
+    NoRegenerationAttempts,
+}
+
+impl Display for GeneratorConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GeneratorConfigError::EmptyMap { width, height } => write!(
+                f,
+                "the map must have at least one row and one column, found {}x{}",
+                width, height
+            ),
+            GeneratorConfigError::NoMountains => {
+                write!(f, "at least one mountain or mountain range is needed")
+            }
+            GeneratorConfigError::InvalidKernelMix(reason) => {
+                write!(f, "invalid kernel mix: {}", reason)
+            }
+            GeneratorConfigError::InvalidRidge(reason) => {
+                write!(f, "invalid mountain range: {}", reason)
+            }
+            GeneratorConfigError::InvalidProminence(prominence) => write!(
+                f,
+                "the prominence must be above 0 and at most 1, found {}",
+                prominence
+            ),
+            GeneratorConfigError::InvalidScale(scale) => {
+                write!(f, "the scale must be a positive number, found {}", scale)
+            }
+            GeneratorConfigError::InvalidInterpolation(interpolation) => write!(
+                f,
+                "the interpolation must be between 0 and 1, found {}",
+                interpolation
+            ),
+            GeneratorConfigError::InvalidBlendSharpness(sharpness) => write!(
+                f,
+                "the smooth max sharpness must be a positive number, found {}",
+                sharpness
+            ),
+            GeneratorConfigError::NonPositiveVariance(min_variance) => write!(
+                f,
+                "the minimum variance must be a positive number, found {}",
+                min_variance
+            ),
+            GeneratorConfigError::InvalidVarianceRange {
+                min_variance,
+                max_variance,
+            } => write!(
+                f,
+                "the minimum variance ({}) must be lower than the maximum variance ({})",
+                min_variance, max_variance
+            ),
+            GeneratorConfigError::InvalidNormalization(message) => {
+                write!(f, "the normalization is invalid: {}", message)
+            }
+            GeneratorConfigError::InvalidErosion(message) => {
+                write!(f, "the erosion is invalid: {}", message)
+            }
+            GeneratorConfigError::InvalidHydrology(message) => {
+                write!(f, "the hydrology is invalid: {}", message)
+            }
+            GeneratorConfigError::InvalidBiomeThresholds => write!(
+                f,
+                "the biome thresholds must be increasing and between 0 and 1"
+            ),
+            GeneratorConfigError::InvalidContentRule { index, reason } => {
+                write!(f, "content rule {} is invalid: {}", index, reason)
+            }
+            GeneratorConfigError::InvalidClimateModel(message) => {
+                write!(f, "the climate model is invalid: {}", message)
+            }
+            GeneratorConfigError::InvalidWeather(message) => {
+                write!(f, "the weather configuration is invalid: {}", message)
+            }
+            GeneratorConfigError::SpawnOutOfBounds { row, col } => {
+                write!(
+                    f,
+                    "the spawn point ({}, {}) is outside of the map",
+                    row, col
+                )
+            }
+            GeneratorConfigError::InvalidMinReachable(min_reachable) => write!(
+                f,
+                "the reachable fraction must be between 0 and 1, found {}",
+                min_reachable
+            ),
+            GeneratorConfigError::NoRegenerationAttempts => {
+                write!(f, "regenerating needs at least one attempt")
+            }
+        }
+    }
+}
+
+impl std::error::Error for GeneratorConfigError {}
+
+/// Builder for a `WorldGenerator` whose configuration is checked before use.
+/// Starts from a 100x100 map with 20 mountains, scale 30, interpolation 0.1 and variances between 5 and 20.
+pub struct WorldGeneratorBuilder {
+    generator: WorldGenerator,
+    /// Why the last weather configuration was refused, reported by `build()`
+    refused_weather: Option<String>,
+}
+
+impl Default for WorldGeneratorBuilder {
+    fn default() -> Self {
+        Self {
+            generator: WorldGenerator::new(100, 20, 30.0, 0.1, 20.0, 5.0),
+            refused_weather: None,
+        }
+    }
+}
+
+impl WorldGeneratorBuilder {
+    /// Sets the size of the square map.
+    pub fn map_size(self, map_size: usize) -> Self {
+        self.dimension(Dimension {
+            width: map_size,
+            height: map_size,
+        })
+    }
+
+    /// Sets the dimension of the map.
+    pub fn dimension(mut self, dimension: Dimension) -> Self {
+        self.generator = self.generator.with_dimension(dimension);
+        self
+    }
+
+    /// Sets the amount of gaussians to be spawned.
+    pub fn amount_mountains(mut self, amount_mountains: usize) -> Self {
+        self.generator.amount_mountains = amount_mountains;
+        self
+    }
+
+    /// Sets the amount of mountain ranges to be spawned.
+    pub fn amount_ridges(mut self, amount_ridges: usize) -> Self {
+        self.generator.amount_ridges = amount_ridges;
+        self
+    }
+
+    /// See `WorldGenerator::with_kernels()`.
+    pub fn kernels(mut self, kernels: KernelMix) -> Self {
+        self.generator = self.generator.with_kernels(kernels);
+        self
+    }
+
+    /// See `WorldGenerator::with_valleys()`.
+    pub fn valleys(mut self, valleys: usize) -> Self {
+        self.generator = self.generator.with_valleys(valleys);
+        self
+    }
+
+    /// See `WorldGenerator::with_min_mountains()`.
+    pub fn min_mountains(mut self, min_mountains: usize) -> Self {
+        self.generator = self.generator.with_min_mountains(min_mountains);
+        self
+    }
+
+    /// See `WorldGenerator::with_prominence()`.
+    pub fn prominence(mut self, prominence: f32) -> Self {
+        self.generator = self.generator.with_prominence(prominence);
+        self
+    }
+
+    /// See `WorldGenerator::with_passes()`.
+    pub fn passes(mut self, passes: usize) -> Self {
+        self.generator = self.generator.with_passes(passes);
+        self
+    }
+
+    /// See `WorldGenerator::with_ridge()`.
+    pub fn ridge(mut self, ridge: Ridge) -> Self {
+        self.generator = self.generator.with_ridge(ridge);
+        self
+    }
+
+    /// Sets the scale of the gaussians.
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.generator.scale = scale;
+        self
+    }
+
+    /// Sets the impact of gaussians behind the highest on the elevation.
+    pub fn interpolation(mut self, interpolation: f32) -> Self {
+        self.generator.interpolation = interpolation;
+        self
+    }
+
+    /// Sets the range of variances in each direction to draw gaussians from.
+    pub fn variance(mut self, min_variance: f32, max_variance: f32) -> Self {
+        self.generator.min_variance = min_variance;
+        self.generator.max_variance = max_variance;
+        self
+    }
+
+    /// Sets the lowest variance in each direction to draw gaussians from, keeping the highest one.
+    pub fn min_variance(mut self, min_variance: f32) -> Self {
+        self.generator.min_variance = min_variance;
+        self
+    }
+
+    /// Sets the highest variance in each direction to draw gaussians from, keeping the lowest one.
+    pub fn max_variance(mut self, max_variance: f32) -> Self {
+        self.generator.max_variance = max_variance;
+        self
+    }
+
+    /// See `WorldGenerator::with_preset()`.
+    pub fn preset(mut self, preset: Preset) -> Self {
+        self.refused_weather = None;
+        self.generator = self.generator.with_preset(preset);
+        self
+    }
+
+    /// See `WorldGenerator::with_blend()`.
+    pub fn blend(mut self, blend: BlendMode) -> Self {
+        self.generator = self.generator.with_blend(blend);
+        self
+    }
+
+    /// See `WorldGenerator::with_seed()`.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.generator = self.generator.with_seed(seed);
+        self
+    }
+
+    /// See `WorldGenerator::with_layer()`.
+    pub fn layer(mut self, op: BlendOp, source: Box<dyn HeightSource>) -> Self {
+        self.generator = self.generator.with_layer(op, source);
+        self
+    }
+
+    /// See `WorldGenerator::with_height_map()`.
+    pub fn height_map(mut self, height_map: HeightMap) -> Self {
+        self.generator = self.generator.with_height_map(height_map);
+        self
+    }
+
+    /// See `WorldGenerator::with_erosion()`.
+    pub fn erosion(mut self, erosion: Option<ErosionParams>) -> Self {
+        self.generator = self.generator.with_erosion(erosion);
+        self
+    }
+
+    /// See `WorldGenerator::with_normalize()`.
+    pub fn normalize(mut self, normalize: Option<NormalizeParams>) -> Self {
+        self.generator = self.generator.with_normalize(normalize);
+        self
+    }
+
+    /// See `WorldGenerator::with_biomes()`.
+    pub fn biomes(mut self, biomes: BiomeThresholds) -> Self {
+        self.generator = self.generator.with_biomes(biomes);
+        self
+    }
+
+    /// See `WorldGenerator::with_climate_model()`.
+    pub fn climate_model(mut self, climate_model: Option<ClimateParams>) -> Self {
+        self.generator = self.generator.with_climate_model(climate_model);
+        self
+    }
+
+    /// See `WorldGenerator::with_hydrology()`.
+    pub fn hydrology(mut self, hydrology: Option<HydrologyParams>) -> Self {
+        self.generator = self.generator.with_hydrology(hydrology);
+        self
+    }
+
+    /// See `WorldGenerator::with_content()`.
+    pub fn content(mut self, content: Vec<ContentRule>) -> Self {
+        self.generator = self.generator.with_content(content);
+        self
+    }
+
+    /// See `WorldGenerator::with_weather()`.
+    pub fn weather(mut self, weather: WeatherConfig) -> Self {
+        self.refused_weather = weather.conditions().err();
+        self.generator = self.generator.with_weather(weather);
+        self
+    }
+
+    /// See `WorldGenerator::with_climate()`.
+    pub fn climate(mut self, climate: Climate) -> Self {
+        self.refused_weather = None;
+        self.generator = self.generator.with_climate(climate);
+        self
+    }
+
+    /// See `WorldGenerator::with_spawn()`.
+    pub fn spawn(mut self, spawn: SpawnStrategy) -> Self {
+        self.generator = self.generator.with_spawn(spawn);
+        self
+    }
+
+    /// See `WorldGenerator::with_max_step()`.
+    pub fn max_step(mut self, max_step: Option<usize>) -> Self {
+        self.generator = self.generator.with_max_step(max_step);
+        self
+    }
+
+    /// See `WorldGenerator::with_connectivity()`.
+    pub fn connectivity(mut self, connectivity: Option<ConnectivityParams>) -> Self {
+        self.generator = self.generator.with_connectivity(connectivity);
+        self
+    }
+
+    /// Checks the configuration and returns the generator.
+    pub fn build(self) -> Result<WorldGenerator, GeneratorConfigError> {
+        if let Some(message) = self.refused_weather {
+            return Err(GeneratorConfigError::InvalidWeather(message));
+        }
+        validate(&self.generator)?;
+        Ok(self.generator)
+    }
+}
+
+/// Checks every parameter of the generator, returning the first invalid one found.
+pub fn validate(generator: &WorldGenerator) -> Result<(), GeneratorConfigError> {
+    let Dimension { width, height } = generator.dimension;
+    if width == 0 || height == 0 {
+        return Err(GeneratorConfigError::EmptyMap { width, height });
+    }
+
+    // The gaussians are not sampled when a height map is given
+    if generator.height_map.is_none() {
+        if generator.amount_mountains == 0
+            && generator.amount_ridges == 0
+            && generator.ridges.is_empty()
+        {
+            return Err(GeneratorConfigError::NoMountains);
+        }
+        generator
+            .kernels
+            .check()
+            .map_err(GeneratorConfigError::InvalidKernelMix)?;
+        check_gaussians(
+            generator.scale,
+            generator.interpolation,
+            generator.min_variance,
+            generator.max_variance,
+        )?;
+        if let BlendMode::SmoothMax { sharpness } = generator.blend {
+            if !sharpness.is_finite() || sharpness <= 0.0 {
+                return Err(GeneratorConfigError::InvalidBlendSharpness(sharpness));
+            }
+        }
+    } else if generator.landforms.min_mountains > 0 {
+        // Missing mountains are still added to the given height map
+        check_variances(generator.min_variance, generator.max_variance)?;
+    }
+
+    let prominence = generator.landforms.prominence;
+    if prominence.is_nan() || prominence <= 0.0 || prominence > 1.0 {
+        return Err(GeneratorConfigError::InvalidProminence(prominence));
+    }
+
+    if let Some(erosion) = &generator.erosion {
+        erosion
+            .check()
+            .map_err(GeneratorConfigError::InvalidErosion)?;
+    }
+
+    if let Some(normalize) = &generator.normalize {
+        if normalize.min_elevation > normalize.max_elevation {
+            return Err(GeneratorConfigError::InvalidNormalization(format!(
+                "minimum elevation {} is above maximum elevation {}",
+                normalize.min_elevation, normalize.max_elevation
+            )));
+        }
+        normalize
+            .curve
+            .check()
+            .map_err(GeneratorConfigError::InvalidNormalization)?;
+    }
+
+    let biomes = &generator.biomes;
+    let thresholds = [
+        0.0,
+        biomes.deep_water,
+        biomes.shallow_water,
+        biomes.sand,
+        biomes.grass,
+        biomes.hill,
+        biomes.mountain,
+        1.0,
+    ];
+    if thresholds.iter().any(|threshold| threshold.is_nan())
+        || thresholds.windows(2).any(|pair| pair[0] > pair[1])
+    {
+        return Err(GeneratorConfigError::InvalidBiomeThresholds);
+    }
+
+    if let Some(hydrology) = &generator.hydrology {
+        hydrology
+            .check()
+            .map_err(GeneratorConfigError::InvalidHydrology)?;
+    }
+
+    for (index, rule) in generator.content.iter().enumerate() {
+        if !(0.0..=1.0).contains(&rule.density) {
+            return Err(GeneratorConfigError::InvalidContentRule {
+                index,
+                reason: format!("density {} is not between 0 and 1", rule.density),
+            });
+        }
+        if rule.quantity.is_empty() {
+            return Err(GeneratorConfigError::InvalidContentRule {
+                index,
+                reason: format!("quantity range {:?} is empty", rule.quantity),
+            });
+        }
+    }
+
+    if let Some(climate_model) = &generator.climate_model {
+        climate_model
+            .check()
+            .map_err(GeneratorConfigError::InvalidClimateModel)?;
+    }
+
+    if let SpawnStrategy::Fixed((row, col)) = generator.spawn {
+        if row >= height || col >= width {
+            return Err(GeneratorConfigError::SpawnOutOfBounds { row, col });
+        }
+    }
+
+    if let Some(connectivity) = &generator.connectivity {
+        if !(0.0..=1.0).contains(&connectivity.min_reachable) {
+            return Err(GeneratorConfigError::InvalidMinReachable(
+                connectivity.min_reachable,
+            ));
+        }
+        if let ConnectivityMode::Regenerate { attempts: 0 } = connectivity.mode {
+            return Err(GeneratorConfigError::NoRegenerationAttempts);
+        }
+    }
+    Ok(())
+}
+
+/// Checks the parameters gaussians are drawn from, shared by WorldGenerator and chunk::ChunkGenerator.
+pub(crate) fn check_gaussians(
+    scale: f32,
+    interpolation: f32,
+    min_variance: f32,
+    max_variance: f32,
+) -> Result<(), GeneratorConfigError> {
+    // NaN is not finite, so it is refused too
+    if !scale.is_finite() || scale <= 0.0 {
+        return Err(GeneratorConfigError::InvalidScale(scale));
+    }
+    if !(0.0..=1.0).contains(&interpolation) {
+        return Err(GeneratorConfigError::InvalidInterpolation(interpolation));
+    }
+    check_variances(min_variance, max_variance)
+}
+
+/// Checks the range the variances of the gaussians are drawn from.
+fn check_variances(min_variance: f32, max_variance: f32) -> Result<(), GeneratorConfigError> {
+    if min_variance.is_nan() || min_variance <= 0.0 {
+        return Err(GeneratorConfigError::NonPositiveVariance(min_variance));
+    }
+    if !max_variance.is_finite() || min_variance >= max_variance {
+        return Err(GeneratorConfigError::InvalidVarianceRange {
+            min_variance,
+            max_variance,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::erosion::{HydraulicErosionParams, ThermalErosionParams};
+
+    #[test]
+    fn defaults_are_valid() {
+        assert!(WorldGenerator::builder().build().is_ok());
+        for preset in [
+            Preset::Plains,
+            Preset::RollingHills,
+            Preset::Alpine,
+            Preset::Archipelago,
+            Preset::Canyonlands,
+            Preset::SingleVolcano,
+        ] {
+            assert!(
+                WorldGenerator::builder().preset(preset).build().is_ok(),
+                "{:?}",
+                preset
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_erosion_is_refused() {
+        let thermal = ErosionParams {
+            thermal: Some(ThermalErosionParams {
+                rate: 1.5,
+                ..Default::default()
+            }),
+            hydraulic: None,
+        };
+        let hydraulic = ErosionParams {
+            thermal: None,
+            hydraulic: Some(HydraulicErosionParams {
+                iterations: 0,
+                ..Default::default()
+            }),
+        };
+        for erosion in [thermal, hydraulic] {
+            assert!(matches!(
+                WorldGenerator::builder().erosion(Some(erosion)).build(),
+                Err(GeneratorConfigError::InvalidErosion(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn invalid_hydrology_is_refused() {
+        let hydrology = HydrologyParams {
+            river_threshold: 1,
+            ..Default::default()
+        };
+        assert!(matches!(
+            WorldGenerator::builder().hydrology(Some(hydrology)).build(),
+            Err(GeneratorConfigError::InvalidHydrology(_))
+        ));
+    }
+
+    #[test]
+    fn added_mountains_need_valid_variances() {
+        let height_map = HeightMap::filled(10, 10, 5);
+        assert!(matches!(
+            WorldGenerator::builder()
+                .height_map(height_map.clone())
+                .min_mountains(1)
+                .variance(10.0, 5.0)
+                .build(),
+            Err(GeneratorConfigError::InvalidVarianceRange { .. })
+        ));
+        assert!(WorldGenerator::builder()
+            .height_map(height_map)
+            .variance(10.0, 5.0)
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn refused_weather_is_reported() {
+        let weather = WeatherConfig {
+            forecast: Vec::new(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            WorldGenerator::builder().weather(weather.clone()).build(),
+            Err(GeneratorConfigError::InvalidWeather(_))
+        ));
+        assert!(WorldGenerator::builder()
+            .weather(weather)
+            .climate(Climate::Arid)
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn invalid_variances_are_refused() {
+        assert!(matches!(
+            WorldGenerator::builder().min_variance(30.0).build(),
+            Err(GeneratorConfigError::InvalidVarianceRange { .. })
+        ));
+        assert!(matches!(
+            WorldGenerator::builder().variance(0.0, 10.0).build(),
+            Err(GeneratorConfigError::NonPositiveVariance(_))
+        ));
+    }
+}
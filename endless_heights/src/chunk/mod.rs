@@ -0,0 +1,368 @@
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand::SeedableRng;
+use robotics_lib::world::tile::{Content, Tile};
+
+use crate::biome::BiomeThresholds;
+use crate::builder::{self, GeneratorConfigError};
+use crate::content::{self, ContentRule};
+use crate::height::{self, BlendMode, Gaussian};
+use crate::source::{BlendOp, HeightSource};
+use crate::World;
+
+/// # Chunk Generator
+///
+/// Generates an endless world one square chunk at a time, so that large worlds can be streamed or sampled without allocating the whole grid.
+///
+/// The plane is divided into cells the size of a chunk, and each cell holds its own gaussians, drawn from an RNG seeded with the world seed and the cell coordinates.
+/// The elevation of a tile only depends on the gaussians of the cells close enough to reach it, so chunks are seamless across their borders
+/// and `elevation_at()` returns exactly the elevation the tile has in its chunk.
+///
+/// Tile types are picked from elevation bands (see `BiomeThresholds`), where elevations are expressed as a fraction of `scale`.
+/// Content follows a set of `ContentRule`s, drawn for each tile from an RNG seeded with the world seed and the tile coordinates.
+/// Stages that need the whole map, such as erosion, normalization and hydrology, are not available.
+///
+/// ## Parameters
+/// - chunk_size: The width and height of a chunk, in tiles.
+/// - mountains_per_chunk: The average amount of gaussians spawned per chunk area. Fractions are drawn at random.
+/// - scale: Scale of the gaussians.
+/// - interpolation: The impact of gaussians behind the highest on the elevation.
+/// - max_variance: The maximum variance in each direction to draw gaussians from.
+/// - min_variance: The minimum variance in each direction to draw gaussians from.
+///
+/// Chunk and tile coordinates follow the world convention: x runs along the rows, y along the columns.
+/// Tile (x, y) belongs to chunk (x.div_euclid(chunk_size), y.div_euclid(chunk_size)).
+pub struct ChunkGenerator {
+    chunk_size: usize,
+    mountains_per_chunk: f32,
+    scale: f32,
+    interpolation: f32,
+    max_variance: f32,
+    min_variance: f32,
+    blend: BlendMode,
+    biomes: BiomeThresholds,
+    content: Vec<ContentRule>,
+    layers: Vec<(BlendOp, Box<dyn HeightSource>)>,
+}
+
+impl ChunkGenerator {
+    /// Creates a chunk generator, or returns the first invalid parameter found.
+    pub fn new(
+        chunk_size: usize,
+        mountains_per_chunk: f32,
+        scale: f32,
+        interpolation: f32,
+        max_variance: f32,
+        min_variance: f32,
+    ) -> Result<ChunkGenerator, GeneratorConfigError> {
+        if chunk_size == 0 {
+            return Err(GeneratorConfigError::EmptyMap {
+                width: chunk_size,
+                height: chunk_size,
+            });
+        }
+        // NaN is not finite, so it is refused too
+        if !mountains_per_chunk.is_finite() || mountains_per_chunk <= 0.0 {
+            return Err(GeneratorConfigError::NoMountains);
+        }
+        builder::check_gaussians(scale, interpolation, min_variance, max_variance)?;
+        Ok(ChunkGenerator {
+            chunk_size,
+            mountains_per_chunk,
+            scale,
+            interpolation,
+            max_variance,
+            min_variance,
+            blend: BlendMode::default(),
+            biomes: BiomeThresholds::default(),
+            content: content::default_rules(),
+            layers: Vec::new(),
+        })
+    }
+
+    /// Sets how overlapping gaussians are combined.
+    pub fn with_blend(mut self, blend: BlendMode) -> ChunkGenerator {
+        self.blend = blend;
+        self
+    }
+
+    /// Sets the elevation bands used to pick tile types.
+    pub fn with_biomes(mut self, biomes: BiomeThresholds) -> ChunkGenerator {
+        self.biomes = biomes;
+        self
+    }
+
+    /// Sets the rules used to scatter content.
+    pub fn with_content(mut self, content: Vec<ContentRule>) -> ChunkGenerator {
+        self.content = content;
+        self
+    }
+
+    /// Adds a height source on top of the gaussians, evaluated at world coordinates.
+    pub fn with_layer(mut self, op: BlendOp, layer: Box<dyn HeightSource>) -> ChunkGenerator {
+        self.layers.push((op, layer));
+        self
+    }
+
+    /// Returns the width and height of a chunk, in tiles.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Returns the elevation of tile (x, y) of the world generated with `seed`.
+    pub fn elevation_at(&self, seed: u64, x: i64, y: i64) -> usize {
+        let cells = self.cells(seed, x, x + 1, y, y + 1);
+        self.elevation(&cells, x, y)
+    }
+
+    /// Generates the tiles of chunk (chunk_x, chunk_y) of the world generated with `seed`.
+    pub fn generate_chunk(&self, seed: u64, chunk_x: i64, chunk_y: i64) -> World {
+        let size = self.chunk_size as i64;
+        let (x0, y0) = (chunk_x * size, chunk_y * size);
+        // Elevations are computed one tile beyond the chunk, for the slopes of the border tiles
+        let cells = self.cells(seed, x0 - 1, x0 + size + 1, y0 - 1, y0 + size + 1);
+        let elevations = (x0 - 1..x0 + size + 1)
+            .map(|x| {
+                (y0 - 1..y0 + size + 1)
+                    .map(|y| self.elevation(&cells, x, y))
+                    .collect::<Vec<usize>>()
+            })
+            .collect::<Vec<Vec<usize>>>();
+
+        let mut world = Vec::with_capacity(self.chunk_size);
+        for i in 1..=self.chunk_size {
+            let mut row = Vec::with_capacity(self.chunk_size);
+            for j in 1..=self.chunk_size {
+                let elevation = elevations[i][j];
+                let tile_type = self
+                    .biomes
+                    .tile_type_for(elevation as f32 / f32::max(self.scale, 1.0));
+                let slope = [
+                    elevations[i - 1][j],
+                    elevations[i + 1][j],
+                    elevations[i][j - 1],
+                    elevations[i][j + 1],
+                ]
+                .iter()
+                .map(|neighbour| neighbour.abs_diff(elevation))
+                .max()
+                .unwrap_or(0);
+                let (x, y) = (x0 + i as i64 - 1, y0 + j as i64 - 1);
+                let mut rng = StdRng::seed_from_u64(mix(seed ^ CONTENT_SALT, x, y));
+                let content = content::pick(&mut rng, &tile_type, slope, &self.content)
+                    .map_or(Content::None, |(content, _)| content);
+                row.push(Tile {
+                    tile_type,
+                    content,
+                    elevation,
+                });
+            }
+            world.push(row);
+        }
+        world
+    }
+
+    /// Returns the farthest distance, in tiles, from which a gaussian can raise a tile.
+    fn reach(&self) -> i64 {
+        f32::ceil(height::gaussian_reach(self.scale, self.max_variance)) as i64
+    }
+
+    /// Samples the gaussians of every cell able to reach a tile between rows x0..x1 and columns y0..y1.
+    fn cells(&self, seed: u64, x0: i64, x1: i64, y0: i64, y1: i64) -> Cells {
+        let size = self.chunk_size as i64;
+        let reach = self.reach();
+        let (cx0, cx1) = (
+            (x0 - reach).div_euclid(size),
+            (x1 - 1 + reach).div_euclid(size),
+        );
+        let (cy0, cy1) = (
+            (y0 - reach).div_euclid(size),
+            (y1 - 1 + reach).div_euclid(size),
+        );
+        let mut gaussians = Vec::new();
+        for cx in cx0..=cx1 {
+            for cy in cy0..=cy1 {
+                gaussians.push(self.cell(seed, cx, cy));
+            }
+        }
+        Cells {
+            cx0,
+            cy0,
+            cols: (cy1 - cy0 + 1) as usize,
+            gaussians,
+        }
+    }
+
+    /// Samples the gaussians of cell (cx, cy), relative to the cell's first tile.
+    fn cell(&self, seed: u64, cx: i64, cy: i64) -> Vec<Gaussian> {
+        let mut rng = StdRng::seed_from_u64(mix(seed, cx, cy));
+        let mut amount = self.mountains_per_chunk.floor() as usize;
+        if rng.gen::<f32>() < self.mountains_per_chunk.fract() {
+            amount += 1;
+        }
+        let size = self.chunk_size as f32;
+        (0..amount)
+            .map(|_| {
+                height::sample_gaussian(
+                    &mut rng,
+                    self.scale,
+                    size,
+                    size,
+                    self.min_variance,
+                    self.max_variance,
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the elevation of tile (x, y) from the gaussians of the cells around it.
+    /// Cells are always visited in the same order, so the result does not depend on which cells were loaded.
+    fn elevation(&self, cells: &Cells, x: i64, y: i64) -> usize {
+        let size = self.chunk_size as i64;
+        let reach = self.reach();
+        let mut values = Vec::new();
+        for cx in (x - reach).div_euclid(size)..=(x + reach).div_euclid(size) {
+            for cy in (y - reach).div_euclid(size)..=(y + reach).div_euclid(size) {
+                let (lx, ly) = ((x - cx * size) as f32, (y - cy * size) as f32);
+                for gaussian in cells.get(cx, cy) {
+                    if (lx - gaussian.mean_x).abs() <= gaussian.extent_x
+                        && (ly - gaussian.mean_y).abs() <= gaussian.extent_y
+                    {
+                        values.push(gaussian.get_value_at(lx, ly));
+                    }
+                }
+            }
+        }
+        let (x, y) = (x as f32, y as f32);
+        self.layers
+            .iter()
+            .fold(
+                self.blend.combine(&values, 0, self.interpolation),
+                |elevation, (op, layer)| op.apply(elevation, layer.height_at(x, y), x, y),
+            )
+            .max(0.0) as usize
+    }
+}
+
+/// Gaussians of a rectangle of cells, starting at cell (cx0, cy0).
+struct Cells {
+    cx0: i64,
+    cy0: i64,
+    cols: usize,
+    gaussians: Vec<Vec<Gaussian>>,
+}
+
+impl Cells {
+    fn get(&self, cx: i64, cy: i64) -> &[Gaussian] {
+        let index = (cx - self.cx0) as usize * self.cols + (cy - self.cy0) as usize;
+        &self.gaussians[index]
+    }
+}
+
+/// Distinguishes the content RNG of a tile from the gaussian RNG of the cell with the same coordinates.
+const CONTENT_SALT: u64 = 0x636f_6e74_656e_7473;
+
+/// Mixes a seed with two coordinates into a new seed (SplitMix64 finalizer).
+fn mix(seed: u64, a: i64, b: i64) -> u64 {
+    let mut z = seed
+        .wrapping_add((a as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+        .wrapping_add((b as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f));
+    for _ in 0..2 {
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+    }
+    z
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: usize = 16;
+
+    fn generator() -> ChunkGenerator {
+        ChunkGenerator::new(SIZE, 3.0, 30.0, 0.1, 8.0, 3.0).unwrap()
+    }
+
+    #[test]
+    fn invalid_parameters_are_refused() {
+        assert!(matches!(
+            ChunkGenerator::new(0, 3.0, 30.0, 0.1, 8.0, 3.0),
+            Err(GeneratorConfigError::EmptyMap { .. })
+        ));
+        assert!(matches!(
+            ChunkGenerator::new(SIZE, 0.0, 30.0, 0.1, 8.0, 3.0),
+            Err(GeneratorConfigError::NoMountains)
+        ));
+        assert!(matches!(
+            ChunkGenerator::new(SIZE, 3.0, f32::NAN, 0.1, 8.0, 3.0),
+            Err(GeneratorConfigError::InvalidScale(_))
+        ));
+        assert!(matches!(
+            ChunkGenerator::new(SIZE, 3.0, 30.0, 0.1, 3.0, 8.0),
+            Err(GeneratorConfigError::InvalidVarianceRange { .. })
+        ));
+        assert!(matches!(
+            ChunkGenerator::new(SIZE, 3.0, 30.0, 0.1, 8.0, 0.0),
+            Err(GeneratorConfigError::NonPositiveVariance(_))
+        ));
+    }
+
+    #[test]
+    fn chunks_are_deterministic() {
+        let generator = generator();
+        assert!(generator.generate_chunk(7, -3, 5) == generator.generate_chunk(7, -3, 5));
+        assert!(generator.generate_chunk(7, -3, 5) != generator.generate_chunk(8, -3, 5));
+    }
+
+    #[test]
+    fn neighbouring_chunks_agree_on_their_shared_edge() {
+        let generator = generator();
+        let size = SIZE as i64;
+        let chunk = generator.generate_chunk(7, 0, 0);
+        let below = generator.generate_chunk(7, 1, 0);
+        let right = generator.generate_chunk(7, 0, 1);
+        for k in 0..SIZE {
+            // Each chunk matches the elevations queried tile by tile on both sides of the edge
+            assert_eq!(
+                chunk[SIZE - 1][k].elevation,
+                generator.elevation_at(7, size - 1, k as i64)
+            );
+            assert_eq!(
+                below[0][k].elevation,
+                generator.elevation_at(7, size, k as i64)
+            );
+            assert_eq!(
+                chunk[k][SIZE - 1].elevation,
+                generator.elevation_at(7, k as i64, size - 1)
+            );
+            assert_eq!(
+                right[k][0].elevation,
+                generator.elevation_at(7, k as i64, size)
+            );
+        }
+
+        // The steps across the edges are no steeper than the ones inside the chunks
+        let inner = (0..SIZE)
+            .flat_map(|i| (1..SIZE).map(move |j| (i, j)))
+            .map(|(i, j)| chunk[i][j].elevation.abs_diff(chunk[i][j - 1].elevation))
+            .max()
+            .unwrap_or(0);
+        let across = (0..SIZE)
+            .map(|k| {
+                usize::max(
+                    chunk[SIZE - 1][k].elevation.abs_diff(below[0][k].elevation),
+                    chunk[k][SIZE - 1].elevation.abs_diff(right[k][0].elevation),
+                )
+            })
+            .max()
+            .unwrap_or(0);
+        assert!(
+            across <= inner.max(2),
+            "{} across, {} inside",
+            across,
+            inner
+        );
+    }
+}
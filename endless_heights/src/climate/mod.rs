@@ -0,0 +1,433 @@
+use rand::rngs::StdRng;
+use rand::Rng;
+use robotics_lib::world::tile::{Content, TileType};
+
+use crate::biome::{self, BiomeThresholds};
+use crate::content::{with_quantity, ContentRule};
+use crate::hydrology::neighbours;
+use crate::World;
+
+/// Direction the prevailing wind blows from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Wind {
+    /// From the first row towards the last one.
+    North,
+    /// From the last column towards the first one.
+    East,
+    /// From the last row towards the first one.
+    South,
+    /// From the first column towards the last one.
+    #[default]
+    West,
+}
+
+/// Parameters of the climate model. Temperatures and moistures are between 0 (coldest, driest) and 1 (hottest, wettest).
+/// - equator: Row of the warmest latitude, as a fraction between 0 and 1 of the rows. Temperature falls linearly away from it.
+/// - equator_temperature: Temperature at sea level on the equator.
+/// - pole_temperature: Temperature at sea level on the row farthest from the equator.
+/// - lapse_rate: Temperature lost from sea level to the highest tile.
+/// - wind: Direction of the prevailing wind carrying moisture over the map.
+/// - humidity: Moisture of the air entering the map upwind.
+/// - evaporation: Moisture picked up by the air over each water tile.
+/// - rainfall: Fraction of its moisture the air loses over each land tile.
+/// - orographic: Extra fraction lost for each step of elevation the air climbs, as a fraction of the elevation range. Higher values give sharper rain shadows.
+/// - forest_density: Probability between 0 and 1 for each tile of a forest biome to get a tree, on top of the content rules.
+#[derive(Clone, Debug)]
+pub struct ClimateParams {
+    pub equator: f32,
+    pub equator_temperature: f32,
+    pub pole_temperature: f32,
+    pub lapse_rate: f32,
+    pub wind: Wind,
+    pub humidity: f32,
+    pub evaporation: f32,
+    pub rainfall: f32,
+    pub orographic: f32,
+    pub forest_density: f32,
+}
+
+impl Default for ClimateParams {
+    fn default() -> Self {
+        Self {
+            equator: 0.5,
+            equator_temperature: 0.9,
+            pole_temperature: 0.45,
+            lapse_rate: 0.5,
+            wind: Wind::West,
+            humidity: 0.8,
+            evaporation: 0.1,
+            rainfall: 0.01,
+            orographic: 1.0,
+            forest_density: 0.25,
+        }
+    }
+}
+
+impl ClimateParams {
+    /// Returns why the parameters are invalid, if they are.
+    pub fn check(&self) -> Result<(), String> {
+        let fractions = [
+            ("equator", self.equator),
+            ("equator temperature", self.equator_temperature),
+            ("pole temperature", self.pole_temperature),
+            ("lapse rate", self.lapse_rate),
+            ("humidity", self.humidity),
+            ("evaporation", self.evaporation),
+            ("rainfall", self.rainfall),
+            ("forest density", self.forest_density),
+        ];
+        for (name, value) in fractions {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(format!("{} {} is not between 0 and 1", name, value));
+            }
+        }
+        if !self.orographic.is_finite() || self.orographic < 0.0 {
+            return Err(format!(
+                "orographic rainfall {} is not a non negative number",
+                self.orographic
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Whittaker biomes, picked from temperature and moisture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Water,
+    Ice,
+    Tundra,
+    Taiga,
+    ColdDesert,
+    Grassland,
+    TemperateForest,
+    Desert,
+    Savanna,
+    Rainforest,
+}
+
+impl Biome {
+    /// Returns the land biome of the given temperature and moisture.
+    pub fn from_climate(temperature: f32, moisture: f32) -> Biome {
+        if temperature < 0.1 {
+            Biome::Ice
+        } else if temperature < 0.3 {
+            if moisture < 0.35 {
+                Biome::Tundra
+            } else {
+                Biome::Taiga
+            }
+        } else if temperature < 0.65 {
+            if moisture < 0.2 {
+                Biome::ColdDesert
+            } else if moisture < 0.5 {
+                Biome::Grassland
+            } else {
+                Biome::TemperateForest
+            }
+        } else if moisture < 0.25 {
+            Biome::Desert
+        } else if moisture < 0.55 {
+            Biome::Savanna
+        } else {
+            Biome::Rainforest
+        }
+    }
+
+    /// Returns the tile type of the biome on low ground. Water, hills and mountains are left to the elevation bands.
+    pub fn tile_type(&self) -> TileType {
+        match self {
+            Biome::Water => TileType::ShallowWater,
+            Biome::Ice | Biome::Tundra => TileType::Snow,
+            Biome::Taiga
+            | Biome::Grassland
+            | Biome::TemperateForest
+            | Biome::Savanna
+            | Biome::Rainforest => TileType::Grass,
+            Biome::ColdDesert | Biome::Desert => TileType::Sand,
+        }
+    }
+
+    /// Returns whether the biome is covered in trees.
+    pub fn is_forest(&self) -> bool {
+        matches!(
+            self,
+            Biome::Taiga | Biome::TemperateForest | Biome::Rainforest
+        )
+    }
+}
+
+/// Temperature, moisture and biome of every tile, indexed by row then column.
+#[derive(Clone, Debug, Default)]
+pub struct ClimateMap {
+    pub temperature: Vec<Vec<f32>>,
+    pub moisture: Vec<Vec<f32>>,
+    pub biomes: Vec<Vec<Biome>>,
+}
+
+/// Computes the climate of the world from the elevations of its tiles, normalized between the lowest and the highest tile.
+/// Tiles in the water bands of the thresholds are sea, and the temperature falls from sea level up to the highest tile.
+/// Moisture is carried by the wind line by line: the air picks it up over the sea and loses it as rain over land, more so while climbing,
+/// so that the lee side of mountains and the inland far from the sea end up dry.
+pub fn compute(world: &World, thresholds: &BiomeThresholds, params: &ClimateParams) -> ClimateMap {
+    let rows = world.len();
+    let cols = world.first().map_or(0, |row| row.len());
+    let normalize = biome::normalizer(world, thresholds);
+    let normalized = |i: usize, j: usize| normalize(world[i][j].elevation);
+    let is_sea = |i: usize, j: usize| normalized(i, j) <= thresholds.shallow_water;
+
+    let mut temperature = vec![vec![0.0; cols]; rows];
+    let farthest = f32::max(params.equator, 1.0 - params.equator);
+    for (i, row) in temperature.iter_mut().enumerate() {
+        let latitude = if rows > 1 {
+            i as f32 / (rows - 1) as f32
+        } else {
+            params.equator
+        };
+        let polarity = if farthest > 0.0 {
+            (latitude - params.equator).abs() / farthest
+        } else {
+            0.0
+        };
+        let sea_level = params.equator_temperature
+            + polarity * (params.pole_temperature - params.equator_temperature);
+        for (j, temperature) in row.iter_mut().enumerate() {
+            let altitude = ((normalized(i, j) - thresholds.shallow_water)
+                / (1.0 - thresholds.shallow_water))
+                .max(0.0);
+            *temperature = (sea_level - params.lapse_rate * altitude).clamp(0.0, 1.0);
+        }
+    }
+
+    // Lines of tiles in the order the wind crosses them
+    let lines: Vec<Vec<(usize, usize)>> = match params.wind {
+        Wind::North => (0..cols)
+            .map(|j| (0..rows).map(|i| (i, j)).collect())
+            .collect(),
+        Wind::South => (0..cols)
+            .map(|j| (0..rows).rev().map(|i| (i, j)).collect())
+            .collect(),
+        Wind::West => (0..rows)
+            .map(|i| (0..cols).map(|j| (i, j)).collect())
+            .collect(),
+        Wind::East => (0..rows)
+            .map(|i| (0..cols).rev().map(|j| (i, j)).collect())
+            .collect(),
+    };
+    let mut moisture = vec![vec![0.0; cols]; rows];
+    for line in lines {
+        let mut air = params.humidity;
+        let mut previous: Option<f32> = None;
+        for (i, j) in line {
+            let elevation = normalized(i, j);
+            if is_sea(i, j) {
+                air = (air + params.evaporation).min(1.0);
+                moisture[i][j] = 1.0;
+            } else {
+                let climb = previous.map_or(0.0, |previous| (elevation - previous).max(0.0));
+                let rain = air * (params.rainfall + params.orographic * climb).min(1.0);
+                air -= rain;
+                // Rain over flat land from fully moist air counts as fully wet
+                moisture[i][j] = if params.rainfall > 0.0 {
+                    (rain / params.rainfall).min(1.0)
+                } else {
+                    air
+                };
+            }
+            previous = Some(elevation);
+        }
+    }
+    // Spreads moisture across the wind, so that lines do not show
+    for _ in 0..2 {
+        let spread = moisture.clone();
+        for i in 0..rows {
+            for j in 0..cols {
+                let (sum, count) = neighbours(i, j, rows, cols)
+                    .fold((spread[i][j], 1), |(sum, count), (ni, nj)| {
+                        (sum + spread[ni][nj], count + 1)
+                    });
+                moisture[i][j] = sum / count as f32;
+            }
+        }
+    }
+
+    let biomes = (0..rows)
+        .map(|i| {
+            (0..cols)
+                .map(|j| {
+                    if is_sea(i, j) {
+                        Biome::Water
+                    } else {
+                        Biome::from_climate(temperature[i][j], moisture[i][j])
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    ClimateMap {
+        temperature,
+        moisture,
+        biomes,
+    }
+}
+
+/// Sets the tile type of every tile of the world from its biome.
+/// Only the `grass` band takes the tile type of its biome: water, beaches, hills and mountains keep the elevation bands of the thresholds.
+/// Like biome::classify(), this stage has to run after height::bump_world().
+pub fn classify(world: &mut World, thresholds: &BiomeThresholds, climate: &ClimateMap) {
+    let normalize = biome::normalizer(world, thresholds);
+    for (row, biomes) in world.iter_mut().zip(&climate.biomes) {
+        for (tile, biome) in row.iter_mut().zip(biomes) {
+            let normalized_elevation = normalize(tile.elevation);
+            tile.tile_type = if normalized_elevation <= thresholds.sand
+                || normalized_elevation > thresholds.grass
+            {
+                thresholds.tile_type_for(normalized_elevation)
+            } else {
+                biome.tile_type()
+            };
+        }
+    }
+}
+
+/// Plants trees on the empty tiles of forest biomes, following the first tree rule with a non empty quantity range for their quantity, tile types and score.
+/// Nothing is planted when no such rule exists. Returns the score of the planted trees.
+pub fn plant_forests(
+    rng: &mut StdRng,
+    world: &mut World,
+    climate: &ClimateMap,
+    params: &ClimateParams,
+    rules: &[ContentRule],
+) -> f32 {
+    let Some(rule) = rules
+        .iter()
+        .find(|rule| matches!(rule.content, Content::Tree(_)) && !rule.quantity.is_empty())
+    else {
+        return 0.0;
+    };
+    let mut score = 0.0;
+    for (row, biomes) in world.iter_mut().zip(&climate.biomes) {
+        for (tile, biome) in row.iter_mut().zip(biomes) {
+            if !biome.is_forest()
+                || tile.content != Content::None
+                || !rule.biomes.contains(&tile.tile_type)
+            {
+                continue;
+            }
+            if rng.gen::<f32>() < params.forest_density {
+                let quantity = rng.gen_range(rule.quantity.clone());
+                tile.content = with_quantity(&rule.content, quantity);
+                score += quantity as f32 * rule.score;
+            }
+        }
+    }
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use robotics_lib::world::tile::Tile;
+
+    fn world_from(elevations: &[Vec<usize>]) -> World {
+        elevations
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&elevation| Tile {
+                        tile_type: TileType::Grass,
+                        content: Content::None,
+                        elevation,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Sea on the first columns, then lowland rising to a ridge in the middle and falling back to the same lowland.
+    fn ridge_world(base: usize) -> World {
+        let profile = |j: usize| -> usize {
+            match j {
+                0..=4 => 0,
+                5..=11 => 40,
+                12..=19 => 40 + 10 * (j - 11),
+                20..=27 => 40 + 10 * (28 - j),
+                _ => 40,
+            }
+        };
+        world_from(&vec![(0..40).map(|j| base + profile(j)).collect(); 5])
+    }
+
+    #[test]
+    fn lee_side_of_a_ridge_is_drier() {
+        let climate = compute(
+            &ridge_world(0),
+            &BiomeThresholds::default(),
+            &ClimateParams::default(),
+        );
+        // Same elevation and distance from the ridge, on either side of it
+        let (windward, lee) = (climate.moisture[2][10], climate.moisture[2][30]);
+        assert!(lee < windward, "lee {} against windward {}", lee, windward);
+        assert_eq!(climate.biomes[2][0], Biome::Water);
+    }
+
+    #[test]
+    fn climate_ignores_the_lowest_elevation() {
+        let thresholds = BiomeThresholds::default();
+        let params = ClimateParams::default();
+        let low = compute(&ridge_world(0), &thresholds, &params);
+        let raised = compute(&ridge_world(1000), &thresholds, &params);
+        assert_eq!(low.temperature, raised.temperature);
+        assert_eq!(low.moisture, raised.moisture);
+        assert_eq!(low.biomes, raised.biomes);
+    }
+
+    #[test]
+    fn elevation_bands_decide_hills_and_mountains() {
+        assert_eq!(Biome::Tundra.tile_type(), TileType::Snow);
+        assert_eq!(Biome::Taiga.tile_type(), TileType::Grass);
+        let thresholds = BiomeThresholds::default();
+        let mut world = world_from(&[vec![0, 40, 100]]);
+        let climate = ClimateMap {
+            biomes: vec![vec![Biome::Water, Biome::Desert, Biome::Taiga]],
+            ..Default::default()
+        };
+        classify(&mut world, &thresholds, &climate);
+        assert_eq!(world[0][0].tile_type, thresholds.tile_type_for(0.0));
+        assert_eq!(world[0][2].tile_type, thresholds.tile_type_for(1.0));
+        // Between the sand and grass bands, the desert sets the tile type
+        assert_eq!(world[0][1].tile_type, TileType::Sand);
+    }
+
+    #[test]
+    fn forests_skip_empty_quantity_ranges() {
+        let mut world = world_from(&vec![vec![10; 4]; 4]);
+        let climate = ClimateMap {
+            biomes: vec![vec![Biome::Rainforest; 4]; 4],
+            ..Default::default()
+        };
+        let params = ClimateParams {
+            forest_density: 1.0,
+            ..Default::default()
+        };
+        let rules = [ContentRule {
+            content: Content::Tree(0),
+            density: 1.0,
+            quantity: 2..2,
+            biomes: vec![TileType::Grass],
+            max_slope: None,
+            score: 1.0,
+        }];
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(
+            plant_forests(&mut rng, &mut world, &climate, &params, &rules),
+            0.0
+        );
+        assert!(world
+            .iter()
+            .flatten()
+            .all(|tile| tile.content == Content::None));
+    }
+}
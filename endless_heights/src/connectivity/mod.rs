@@ -0,0 +1,213 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+use robotics_lib::world::tile::TileType;
+
+use crate::hydrology::neighbours;
+use crate::spawn::{is_walkable, walkable_regions};
+use crate::World;
+
+/// What to do when too few walkable tiles are reachable from the spawn point.
+#[derive(Clone, Debug)]
+pub enum ConnectivityMode {
+    /// Carves corridors through non walkable tiles to join the disconnected regions.
+    Carve,
+    /// Generates a new world, up to `attempts` times, then carves corridors if the last attempt still falls short.
+    Regenerate { attempts: usize },
+}
+
+/// Parameters of the connectivity stage.
+/// - min_reachable: Fraction between 0 and 1 of the walkable tiles that must be reachable from the spawn point.
+/// - mode: How to fix a world below the threshold.
+/// - carve_cost: Cost of carving a non walkable tile, compared to 1 for walking on a walkable one. Higher values give shorter corridors through water and walls.
+#[derive(Clone, Debug)]
+pub struct ConnectivityParams {
+    pub min_reachable: f32,
+    pub mode: ConnectivityMode,
+    pub carve_cost: usize,
+}
+
+impl Default for ConnectivityParams {
+    fn default() -> Self {
+        Self {
+            min_reachable: 1.0,
+            mode: ConnectivityMode::Carve,
+            carve_cost: 10,
+        }
+    }
+}
+
+/// What the connectivity stage found and did.
+/// - attempts: Amount of worlds generated, 1 unless regenerating.
+/// - disconnected: The walkable regions that were unreachable from the spawn point of the last generated world.
+/// - carved: The tiles turned walkable to join the regions.
+/// - reachable_fraction: Fraction of walkable tiles reachable from the spawn point in the final world.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectivityReport {
+    pub attempts: usize,
+    pub disconnected: Vec<Vec<(usize, usize)>>,
+    pub carved: Vec<(usize, usize)>,
+    pub reachable_fraction: f32,
+}
+
+/// Returns, for each tile, whether it can be reached from the spawn point walking on walkable tiles.
+pub fn reachable_from(world: &World, spawn: (usize, usize)) -> Vec<Vec<bool>> {
+    let rows = world.len();
+    let cols = world.first().map_or(0, |row| row.len());
+    let mut reachable = vec![vec![false; cols]; rows];
+    if spawn.0 >= rows || spawn.1 >= cols || !is_walkable(world, spawn.0, spawn.1) {
+        return reachable;
+    }
+    reachable[spawn.0][spawn.1] = true;
+    let mut queue = VecDeque::from([spawn]);
+    while let Some((i, j)) = queue.pop_front() {
+        for (ni, nj) in neighbours(i, j, rows, cols) {
+            if !reachable[ni][nj] && is_walkable(world, ni, nj) {
+                reachable[ni][nj] = true;
+                queue.push_back((ni, nj));
+            }
+        }
+    }
+    reachable
+}
+
+/// Returns the fraction of walkable tiles reachable from the spawn point, and the walkable regions that are not.
+pub fn analyze(world: &World, spawn: (usize, usize)) -> (f32, Vec<Vec<(usize, usize)>>) {
+    let reachable = reachable_from(world, spawn);
+    let regions = walkable_regions(world);
+    let walkable = regions.iter().map(|region| region.len()).sum::<usize>();
+    let (connected, disconnected): (Vec<_>, Vec<_>) = regions
+        .into_iter()
+        .partition(|region| reachable[region[0].0][region[0].1]);
+    let reached = connected.iter().map(|region| region.len()).sum::<usize>();
+    let fraction = if walkable == 0 {
+        1.0
+    } else {
+        reached as f32 / walkable as f32
+    };
+    (fraction, disconnected)
+}
+
+/// Carves corridors from the spawn region to the closest disconnected region until at least min_reachable of the walkable tiles are reachable.
+/// Corridors follow the cheapest path, where walking costs 1 and carving costs carve_cost.
+/// Returns the carved tiles.
+pub fn carve(
+    world: &mut World,
+    spawn: (usize, usize),
+    params: &ConnectivityParams,
+) -> Vec<(usize, usize)> {
+    let rows = world.len();
+    let cols = world.first().map_or(0, |row| row.len());
+    let mut carved = Vec::new();
+    loop {
+        let (fraction, disconnected) = analyze(world, spawn);
+        if fraction >= params.min_reachable || disconnected.is_empty() {
+            return carved;
+        }
+        let reachable = reachable_from(world, spawn);
+
+        // Dijkstra from every reachable tile, stopping at the first walkable tile outside the spawn region
+        let mut cost = vec![vec![usize::MAX; cols]; rows];
+        let mut previous = vec![vec![None; cols]; rows];
+        let mut queue = BinaryHeap::new();
+        for i in 0..rows {
+            for j in 0..cols {
+                if reachable[i][j] {
+                    cost[i][j] = 0;
+                    queue.push(Reverse((0, i, j)));
+                }
+            }
+        }
+        let mut target = None;
+        while let Some(Reverse((c, i, j))) = queue.pop() {
+            if c > cost[i][j] {
+                continue;
+            }
+            if !reachable[i][j] && is_walkable(world, i, j) {
+                target = Some((i, j));
+                break;
+            }
+            for (ni, nj) in neighbours(i, j, rows, cols) {
+                let step = if is_walkable(world, ni, nj) {
+                    1
+                } else {
+                    params.carve_cost
+                };
+                if c + step < cost[ni][nj] {
+                    cost[ni][nj] = c + step;
+                    previous[ni][nj] = Some((i, j));
+                    queue.push(Reverse((c + step, ni, nj)));
+                }
+            }
+        }
+
+        let Some(mut current) = target else {
+            return carved;
+        };
+        while let Some(prev) = previous[current.0][current.1] {
+            if !is_walkable(world, current.0, current.1) {
+                let tile = &mut world[current.0][current.1];
+                tile.tile_type = match tile.tile_type {
+                    TileType::DeepWater => TileType::ShallowWater,
+                    _ => TileType::Street,
+                };
+                carved.push(current);
+            }
+            current = prev;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use robotics_lib::world::tile::{Content, Tile};
+
+    /// Builds a world from rows of 'g' for grass and 'w' for deep water.
+    fn world_of(rows: &[&str]) -> World {
+        rows.iter()
+            .map(|row| {
+                row.chars()
+                    .map(|c| Tile {
+                        tile_type: if c == 'w' {
+                            TileType::DeepWater
+                        } else {
+                            TileType::Grass
+                        },
+                        content: Content::None,
+                        elevation: 0,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn analyze_finds_the_disconnected_half() {
+        let world = world_of(&["ggwgg", "ggwgg"]);
+        let (fraction, disconnected) = analyze(&world, (0, 0));
+        assert_eq!(fraction, 0.5);
+        assert_eq!(disconnected.len(), 1);
+        assert_eq!(disconnected[0].len(), 4);
+        assert!(!reachable_from(&world, (0, 0))[1][4]);
+    }
+
+    #[test]
+    fn carve_crosses_at_the_narrowest_point() {
+        let mut world = world_of(&["gggwggg", "ggwwwgg", "ggwwwgg"]);
+        let carved = carve(&mut world, (2, 0), &ConnectivityParams::default());
+        assert_eq!(carved, vec![(0, 3)]);
+        assert_eq!(world[0][3].tile_type, TileType::ShallowWater);
+        assert_eq!(analyze(&world, (2, 0)).0, 1.0);
+    }
+
+    #[test]
+    fn carve_stops_at_min_reachable() {
+        let mut world = world_of(&["ggggwg"]);
+        let params = ConnectivityParams {
+            min_reachable: 0.8,
+            ..Default::default()
+        };
+        assert!(carve(&mut world, (0, 0), &params).is_empty());
+    }
+}
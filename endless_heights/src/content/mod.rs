@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use rand::rngs::StdRng;
+use rand::Rng;
+use robotics_lib::world::tile::{Content, TileType};
+
+use crate::hydrology::neighbours;
+use crate::World;
+
+/// Describes how a type of content is scattered over the world.
+/// - content: The content to place. Its quantity is ignored and drawn from `quantity`.
+/// - density: Probability between 0 and 1 for each eligible tile to receive the content.
+/// - quantity: Range the quantity of each placed content is drawn from.
+/// - biomes: Tile types the content can be placed on.
+/// - max_slope: If set, only tiles whose elevation differs from every neighbour by at most this amount are eligible.
+/// - score: Score given for each unit of the content.
+#[derive(Clone, Debug)]
+pub struct ContentRule {
+    pub content: Content,
+    pub density: f32,
+    pub quantity: Range<usize>,
+    pub biomes: Vec<TileType>,
+    pub max_slope: Option<usize>,
+    pub score: f32,
+}
+
+/// Returns the default set of rules: trees in grassland, rocks on mountains, fish in water, coins and garbage scattered around, banks and markets on flat ground.
+pub fn default_rules() -> Vec<ContentRule> {
+    vec![
+        ContentRule {
+            content: Content::Tree(0),
+            density: 0.08,
+            quantity: 1..4,
+            biomes: vec![TileType::Grass, TileType::Hill],
+            max_slope: None,
+            score: 1.0,
+        },
+        ContentRule {
+            content: Content::Rock(0),
+            density: 0.06,
+            quantity: 1..4,
+            biomes: vec![TileType::Hill, TileType::Mountain],
+            max_slope: None,
+            score: 1.0,
+        },
+        ContentRule {
+            content: Content::Fish(0),
+            density: 0.05,
+            quantity: 1..4,
+            biomes: vec![TileType::ShallowWater, TileType::DeepWater],
+            max_slope: None,
+            score: 2.0,
+        },
+        ContentRule {
+            content: Content::Coin(0),
+            density: 0.01,
+            quantity: 1..6,
+            biomes: vec![TileType::Sand, TileType::Grass, TileType::Hill],
+            max_slope: None,
+            score: 5.0,
+        },
+        ContentRule {
+            content: Content::Garbage(0),
+            density: 0.01,
+            quantity: 1..3,
+            biomes: vec![TileType::Sand, TileType::Grass, TileType::Hill],
+            max_slope: None,
+            score: 0.5,
+        },
+        ContentRule {
+            content: Content::Bank(0..0),
+            density: 0.002,
+            quantity: 10..50,
+            biomes: vec![TileType::Sand, TileType::Grass],
+            max_slope: Some(1),
+            score: 10.0,
+        },
+        ContentRule {
+            content: Content::Market(0),
+            density: 0.002,
+            quantity: 1..4,
+            biomes: vec![TileType::Grass],
+            max_slope: Some(1),
+            score: 10.0,
+        },
+    ]
+}
+
+/// Returns the given content with its quantity replaced. Contents holding a range get `0..quantity`.
+pub fn with_quantity(content: &Content, quantity: usize) -> Content {
+    match content {
+        Content::Rock(_) => Content::Rock(quantity),
+        Content::Tree(_) => Content::Tree(quantity),
+        Content::Garbage(_) => Content::Garbage(quantity),
+        Content::Coin(_) => Content::Coin(quantity),
+        Content::Bin(_) => Content::Bin(0..quantity),
+        Content::Crate(_) => Content::Crate(0..quantity),
+        Content::Bank(_) => Content::Bank(0..quantity),
+        Content::Water(_) => Content::Water(quantity),
+        Content::Market(_) => Content::Market(quantity),
+        Content::Fish(_) => Content::Fish(quantity),
+        Content::Bush(_) => Content::Bush(quantity),
+        Content::JollyBlock(_) => Content::JollyBlock(quantity),
+        other => other.clone(),
+    }
+}
+
+/// Places content on the tiles of the world following the given rules.
+/// Rules are tried in order on every empty tile, the first successful roll places its content.
+/// Returns the maximum score obtainable from the placed content and the score table, keyed by content with quantity 0.
+pub fn place(
+    rng: &mut StdRng,
+    world: &mut World,
+    rules: &[ContentRule],
+) -> (f32, HashMap<Content, f32>) {
+    let score_table = rules
+        .iter()
+        .map(|rule| (with_quantity(&rule.content, 0), rule.score))
+        .collect::<HashMap<Content, f32>>();
+
+    let rows = world.len();
+    let cols = world.first().map_or(0, |row| row.len());
+    let mut max_score = 0.0;
+    for i in 0..rows {
+        for j in 0..cols {
+            if world[i][j].content != Content::None {
+                continue;
+            }
+            let slope = neighbours(i, j, rows, cols)
+                .map(|(ni, nj)| world[ni][nj].elevation.abs_diff(world[i][j].elevation))
+                .max()
+                .unwrap_or(0);
+            if let Some((content, score)) = pick(rng, &world[i][j].tile_type, slope, rules) {
+                world[i][j].content = content;
+                max_score += score;
+            }
+        }
+    }
+    (max_score, score_table)
+}
+
+/// Draws the content of a single empty tile, given its tile type and steepest slope to a neighbour.
+/// Rules with an empty quantity range are skipped.
+/// Returns the content with its quantity and the score it is worth, if any rule placed something.
+pub(crate) fn pick(
+    rng: &mut StdRng,
+    tile_type: &TileType,
+    slope: usize,
+    rules: &[ContentRule],
+) -> Option<(Content, f32)> {
+    for rule in rules {
+        if rule.quantity.is_empty()
+            || !rule.biomes.contains(tile_type)
+            || rule.max_slope.is_some_and(|max_slope| slope > max_slope)
+        {
+            continue;
+        }
+        if rng.gen::<f32>() < rule.density {
+            let quantity = rng.gen_range(rule.quantity.clone());
+            return Some((
+                with_quantity(&rule.content, quantity),
+                quantity as f32 * rule.score,
+            ));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use robotics_lib::world::tile::Tile;
+
+    fn grass_world() -> World {
+        (0..10)
+            .map(|_| {
+                (0..10)
+                    .map(|_| Tile {
+                        tile_type: TileType::Grass,
+                        content: Content::None,
+                        elevation: 0,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn tree_rule(quantity: Range<usize>) -> ContentRule {
+        ContentRule {
+            content: Content::Tree(0),
+            density: 1.0,
+            quantity,
+            biomes: vec![TileType::Grass],
+            max_slope: None,
+            score: 2.0,
+        }
+    }
+
+    #[test]
+    fn place_scores_every_placed_unit() {
+        let mut world = grass_world();
+        let mut rng = StdRng::seed_from_u64(0);
+        let (max_score, score_table) = place(&mut rng, &mut world, &[tree_rule(3..4)]);
+        assert_eq!(max_score, 100.0 * 3.0 * 2.0);
+        assert_eq!(score_table.get(&Content::Tree(0)), Some(&2.0));
+        assert!(world
+            .iter()
+            .flatten()
+            .all(|tile| tile.content == Content::Tree(3)));
+    }
+
+    #[test]
+    fn place_skips_empty_quantity_ranges() {
+        let mut world = grass_world();
+        let mut rng = StdRng::seed_from_u64(0);
+        let (max_score, _) = place(&mut rng, &mut world, &[tree_rule(2..2)]);
+        assert_eq!(max_score, 0.0);
+        assert!(world
+            .iter()
+            .flatten()
+            .all(|tile| tile.content == Content::None));
+    }
+}
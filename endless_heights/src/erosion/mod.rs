@@ -0,0 +1,404 @@
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand::SeedableRng;
+
+use crate::height::HeightMap;
+use crate::hydrology::neighbours;
+
+/// Parameters of the thermal erosion.
+/// - iterations: Amount of slumping passes over the whole map.
+/// - talus: Highest elevation difference between neighbouring tiles that is stable. Steeper slopes slump.
+/// - rate: Fraction between 0 and 1 of the excess material moved downhill at each pass.
+#[derive(Clone, Debug)]
+pub struct ThermalErosionParams {
+    pub iterations: usize,
+    pub talus: f32,
+    pub rate: f32,
+}
+
+impl Default for ThermalErosionParams {
+    fn default() -> Self {
+        Self {
+            iterations: 20,
+            talus: 2.0,
+            rate: 0.5,
+        }
+    }
+}
+
+/// Parameters of the particle based hydraulic erosion.
+/// - iterations: Amount of droplets simulated.
+/// - lifetime: Maximum amount of steps a droplet takes before evaporating.
+/// - inertia: Between 0 and 1, how much a droplet keeps its direction instead of following the slope.
+/// - capacity: How much sediment a droplet can carry per unit of speed, water and slope.
+/// - erosion_rate: Fraction of the free capacity a droplet erodes at each step.
+/// - deposition_rate: Fraction of the excess sediment a droplet deposits at each step.
+/// - evaporation_rate: Fraction of water a droplet loses at each step.
+/// - gravity: Acceleration given to droplets going downhill.
+/// - radius: Distance in tiles around a droplet over which it erodes, at least 1. Wider droplets wear slopes down instead of digging narrow gullies.
+/// - seed: Seed of the droplets spawn positions. If not set, droplets are drawn from the generator's rng.
+#[derive(Clone, Debug)]
+pub struct HydraulicErosionParams {
+    pub iterations: usize,
+    pub lifetime: usize,
+    pub inertia: f32,
+    pub capacity: f32,
+    pub erosion_rate: f32,
+    pub deposition_rate: f32,
+    pub evaporation_rate: f32,
+    pub gravity: f32,
+    pub radius: f32,
+    pub seed: Option<u64>,
+}
+
+impl Default for HydraulicErosionParams {
+    fn default() -> Self {
+        Self {
+            iterations: 5000,
+            lifetime: 30,
+            inertia: 0.05,
+            capacity: 4.0,
+            erosion_rate: 0.3,
+            deposition_rate: 0.3,
+            evaporation_rate: 0.02,
+            gravity: 4.0,
+            radius: 3.0,
+            seed: None,
+        }
+    }
+}
+
+/// The erosion passes run by the generator. Thermal erosion runs first.
+#[derive(Clone, Debug, Default)]
+pub struct ErosionParams {
+    pub thermal: Option<ThermalErosionParams>,
+    pub hydraulic: Option<HydraulicErosionParams>,
+}
+
+impl ErosionParams {
+    /// Returns why the parameters are invalid, if they are.
+    pub fn check(&self) -> Result<(), String> {
+        let fraction = |name: &str, value: f32| {
+            if (0.0..=1.0).contains(&value) {
+                Ok(())
+            } else {
+                Err(format!("{} {} is not between 0 and 1", name, value))
+            }
+        };
+        let non_negative = |name: &str, value: f32| {
+            if value.is_finite() && value >= 0.0 {
+                Ok(())
+            } else {
+                Err(format!("{} {} is not a non negative number", name, value))
+            }
+        };
+        if let Some(thermal) = &self.thermal {
+            if thermal.iterations == 0 {
+                return Err("thermal erosion needs at least one iteration".to_string());
+            }
+            non_negative("talus", thermal.talus)?;
+            fraction("thermal rate", thermal.rate)?;
+        }
+        if let Some(hydraulic) = &self.hydraulic {
+            if hydraulic.iterations == 0 || hydraulic.lifetime == 0 {
+                return Err(
+                    "hydraulic erosion needs at least one droplet living one step".to_string(),
+                );
+            }
+            fraction("inertia", hydraulic.inertia)?;
+            non_negative("capacity", hydraulic.capacity)?;
+            fraction("erosion rate", hydraulic.erosion_rate)?;
+            fraction("deposition rate", hydraulic.deposition_rate)?;
+            fraction("evaporation rate", hydraulic.evaporation_rate)?;
+            non_negative("gravity", hydraulic.gravity)?;
+            // NaN is refused too
+            if !(hydraulic.radius.is_finite() && hydraulic.radius >= 1.0) {
+                return Err(format!(
+                    "erosion radius {} is not a number of at least 1",
+                    hydraulic.radius
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs the configured erosion passes on the height map.
+pub fn erode(rng: &mut StdRng, height_map: &mut HeightMap, params: &ErosionParams) {
+    if let Some(thermal_params) = &params.thermal {
+        thermal(height_map, thermal_params);
+    }
+    if let Some(hydraulic_params) = &params.hydraulic {
+        match hydraulic_params.seed {
+            Some(seed) => hydraulic(
+                &mut StdRng::seed_from_u64(seed),
+                height_map,
+                hydraulic_params,
+            ),
+            None => hydraulic(rng, height_map, hydraulic_params),
+        }
+    }
+}
+
+/// Thermal erosion: material on slopes steeper than the talus slumps towards the lower neighbours.
+pub fn thermal(height_map: &mut HeightMap, params: &ThermalErosionParams) {
+    let mut grid = to_grid(height_map);
+    let rows = height_map.rows();
+    let cols = height_map.cols();
+    for _ in 0..params.iterations {
+        let mut delta = vec![vec![0.0; cols]; rows];
+        for i in 0..rows {
+            for j in 0..cols {
+                for (ni, nj) in neighbours(i, j, rows, cols) {
+                    let difference = grid[i][j] - grid[ni][nj];
+                    if difference > params.talus {
+                        // At most half of the excess is moved, split in four as up to four neighbours can be lower, so slopes never flip
+                        let moved = 0.5 * params.rate * (difference - params.talus) / 4.0;
+                        delta[i][j] -= moved;
+                        delta[ni][nj] += moved;
+                    }
+                }
+            }
+        }
+        for i in 0..rows {
+            for j in 0..cols {
+                grid[i][j] += delta[i][j];
+            }
+        }
+    }
+    write_grid(height_map, &grid);
+}
+
+/// Hydraulic erosion: droplets spawned at random run downhill, eroding the terrain while they speed up and depositing sediment where they slow down.
+pub fn hydraulic(rng: &mut StdRng, height_map: &mut HeightMap, params: &HydraulicErosionParams) {
+    let rows = height_map.rows();
+    let cols = height_map.cols();
+    if rows < 2 || cols < 2 {
+        return;
+    }
+    let mut grid = to_grid(height_map);
+    let max_x = (rows - 1) as f32;
+    let max_y = (cols - 1) as f32;
+
+    for _ in 0..params.iterations {
+        let mut x = rng.gen_range(0.0..max_x);
+        let mut y = rng.gen_range(0.0..max_y);
+        let (mut dir_x, mut dir_y) = (0.0, 0.0);
+        let mut speed = 1.0;
+        let mut water = 1.0;
+        let mut sediment = 0.0;
+
+        for _ in 0..params.lifetime {
+            let (cell_x, cell_y) = (x as usize, y as usize);
+            let (offset_x, offset_y) = (x - cell_x as f32, y - cell_y as f32);
+            let (elevation, gradient_x, gradient_y) = sample(&grid, x, y);
+
+            dir_x = dir_x * params.inertia - gradient_x * (1.0 - params.inertia);
+            dir_y = dir_y * params.inertia - gradient_y * (1.0 - params.inertia);
+            let length = f32::sqrt(dir_x * dir_x + dir_y * dir_y);
+            if length == 0.0 {
+                break;
+            }
+            dir_x /= length;
+            dir_y /= length;
+            x += dir_x;
+            y += dir_y;
+            if x < 0.0 || y < 0.0 || x >= max_x || y >= max_y {
+                break;
+            }
+
+            let difference = sample(&grid, x, y).0 - elevation;
+            let capacity = f32::max(-difference * speed * water * params.capacity, 0.01);
+            // Corners of the cell the droplet left, weighted by distance
+            let corners = [
+                (cell_x, cell_y, (1.0 - offset_x) * (1.0 - offset_y)),
+                (cell_x + 1, cell_y, offset_x * (1.0 - offset_y)),
+                (cell_x, cell_y + 1, (1.0 - offset_x) * offset_y),
+                (cell_x + 1, cell_y + 1, offset_x * offset_y),
+            ];
+            if sediment > capacity || difference > 0.0 {
+                let deposit = if difference > 0.0 {
+                    f32::min(difference, sediment)
+                } else {
+                    (sediment - capacity) * params.deposition_rate
+                };
+                sediment -= deposit;
+                for (ci, cj, weight) in corners {
+                    grid[ci][cj] += deposit * weight;
+                }
+            } else {
+                let eroded = f32::min((capacity - sediment) * params.erosion_rate, -difference);
+                // Around the position the droplet left, tiles closer to it are eroded more
+                let (from_x, from_y) = (cell_x as f32 + offset_x, cell_y as f32 + offset_y);
+                let brush = |i: usize, j: usize| {
+                    (params.radius - f32::hypot(i as f32 - from_x, j as f32 - from_y)).max(0.0)
+                };
+                let reach = params.radius.ceil() as usize;
+                let (rows_around, cols_around) = (
+                    cell_x.saturating_sub(reach)..=usize::min(cell_x + reach + 1, rows - 1),
+                    cell_y.saturating_sub(reach)..=usize::min(cell_y + reach + 1, cols - 1),
+                );
+                let total = rows_around
+                    .clone()
+                    .flat_map(|i| cols_around.clone().map(move |j| (i, j)))
+                    .map(|(i, j)| brush(i, j))
+                    .sum::<f32>();
+                for i in rows_around {
+                    for j in cols_around.clone() {
+                        let removed = f32::min(eroded * brush(i, j) / total, grid[i][j]);
+                        grid[i][j] -= removed;
+                        sediment += removed;
+                    }
+                }
+            }
+
+            speed = f32::sqrt(f32::max(speed * speed - difference * params.gravity, 0.0));
+            water *= 1.0 - params.evaporation_rate;
+        }
+    }
+    write_grid(height_map, &grid);
+}
+
+/// Returns the bilinearly interpolated elevation and its gradient at coordinate x, y.
+fn sample(grid: &[Vec<f32>], x: f32, y: f32) -> (f32, f32, f32) {
+    let (i, j) = (x as usize, y as usize);
+    let (u, v) = (x - i as f32, y - j as f32);
+    let h00 = grid[i][j];
+    let h10 = grid[i + 1][j];
+    let h01 = grid[i][j + 1];
+    let h11 = grid[i + 1][j + 1];
+    let gradient_x = (h10 - h00) * (1.0 - v) + (h11 - h01) * v;
+    let gradient_y = (h01 - h00) * (1.0 - u) + (h11 - h10) * u;
+    let elevation =
+        h00 * (1.0 - u) * (1.0 - v) + h10 * u * (1.0 - v) + h01 * (1.0 - u) * v + h11 * u * v;
+    (elevation, gradient_x, gradient_y)
+}
+
+fn to_grid(height_map: &HeightMap) -> Vec<Vec<f32>> {
+    (0..height_map.rows())
+        .map(|i| {
+            (0..height_map.cols())
+                .map(|j| height_map.elevation(i, j) as f32)
+                .collect()
+        })
+        .collect()
+}
+
+fn write_grid(height_map: &mut HeightMap, grid: &[Vec<f32>]) {
+    for (i, row) in grid.iter().enumerate() {
+        for (j, elevation) in row.iter().enumerate() {
+            height_map.set_elevation(i, j, elevation.max(0.0).round() as usize);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thermal_slumps_a_spike_without_flipping_it() {
+        let mut height_map = HeightMap::filled(5, 5, 0);
+        height_map.set_elevation(2, 2, 100);
+        thermal(
+            &mut height_map,
+            &ThermalErosionParams {
+                iterations: 1,
+                talus: 2.0,
+                rate: 1.0,
+            },
+        );
+        let peak = height_map.elevation(2, 2);
+        assert!(peak < 100);
+        for (i, j) in neighbours(2, 2, 5, 5) {
+            assert!(height_map.elevation(i, j) > 0);
+            assert!(height_map.elevation(i, j) < peak);
+        }
+    }
+
+    fn hydraulic_only(seed: u64) -> ErosionParams {
+        ErosionParams {
+            thermal: None,
+            hydraulic: Some(HydraulicErosionParams {
+                iterations: 3000,
+                seed: Some(seed),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn hills() -> HeightMap {
+        let mut rng = StdRng::seed_from_u64(11);
+        let dimension = crate::utils::Dimension {
+            width: 48,
+            height: 40,
+        };
+        crate::height::create_height_map(&mut rng, &dimension, 10, 60.0, 0.1, 3.0, 8.0)
+    }
+
+    #[test]
+    fn hydraulic_wears_down_the_steepest_slopes() {
+        let before = hills();
+        let mut after = hills();
+        erode(
+            &mut StdRng::seed_from_u64(0),
+            &mut after,
+            &hydraulic_only(5),
+        );
+        // Steepest step from each tile to its neighbours
+        let slope = |height_map: &HeightMap, (i, j): (usize, usize)| {
+            neighbours(i, j, height_map.rows(), height_map.cols())
+                .map(|(ni, nj)| {
+                    height_map
+                        .elevation(i, j)
+                        .abs_diff(height_map.elevation(ni, nj))
+                })
+                .max()
+                .unwrap_or(0)
+        };
+        let mut tiles = (0..before.rows())
+            .flat_map(|i| (0..before.cols()).map(move |j| (i, j)))
+            .collect::<Vec<(usize, usize)>>();
+        tiles.sort_by_key(|&tile| std::cmp::Reverse(slope(&before, tile)));
+        let steepest = &tiles[..tiles.len() / 20];
+        let total = |height_map: &HeightMap| {
+            steepest
+                .iter()
+                .map(|&tile| slope(height_map, tile))
+                .sum::<usize>()
+        };
+        assert!(total(&after) < total(&before));
+        // Sediment is only moved around, so no tile rises above the highest one, as a tile saturated by an infinite value would
+        let highest = before.elevations().iter().copied().max().unwrap_or(0);
+        assert!(after
+            .elevations()
+            .iter()
+            .all(|&elevation| elevation <= highest));
+        assert_ne!(after.elevations(), before.elevations());
+    }
+
+    #[test]
+    fn hydraulic_seed_gives_the_same_output() {
+        let mut first = hills();
+        let mut second = hills();
+        let mut other = hills();
+        // The seed of the params takes over from the generator rng
+        erode(
+            &mut StdRng::seed_from_u64(1),
+            &mut first,
+            &hydraulic_only(5),
+        );
+        erode(
+            &mut StdRng::seed_from_u64(2),
+            &mut second,
+            &hydraulic_only(5),
+        );
+        erode(
+            &mut StdRng::seed_from_u64(1),
+            &mut other,
+            &hydraulic_only(6),
+        );
+        assert_eq!(first.elevations(), second.elevations());
+        assert_ne!(first.elevations(), other.elevations());
+    }
+}
@@ -0,0 +1,418 @@
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+
+use super::HeightMap;
+use crate::utils::Dimension;
+
+/// Bit depth of the samples of an image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitDepth {
+    Eight,
+    Sixteen,
+}
+
+impl BitDepth {
+    fn max_value(&self) -> usize {
+        match self {
+            BitDepth::Eight => u8::MAX as usize,
+            BitDepth::Sixteen => u16::MAX as usize,
+        }
+    }
+}
+
+/// Formats height maps can be exported to.
+/// Elevations are stored as they are when they fit the bit depth, otherwise they are scaled down to its full range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Binary grayscale PGM. The maximum value of the header is the highest elevation, so viewers show the full contrast.
+    Pgm(BitDepth),
+    /// Grayscale PNG.
+    Png(BitDepth),
+    /// Headerless little-endian u16 samples, row after row.
+    RawU16,
+}
+
+/// Errors returned when importing or exporting height maps.
+#[derive(Debug)]
+pub enum HeightMapIoError {
+    /// The file could not be read or written
+    Io(std::io::Error),
+    /// The PNG encoder or decoder failed
+    Png(String),
+    /// The data is not a supported image, or does not match the expected dimension
+    InvalidData(String),
+}
+
+impl Display for HeightMapIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeightMapIoError::Io(error) => write!(f, "io error: {}", error),
+            HeightMapIoError::Png(message) => write!(f, "png error: {}", message),
+            HeightMapIoError::InvalidData(message) => write!(f, "invalid data: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for HeightMapIoError {}
+
+impl From<std::io::Error> for HeightMapIoError {
+    fn from(error: std::io::Error) -> Self {
+        HeightMapIoError::Io(error)
+    }
+}
+
+/// Encodes the height map in the given format.
+pub fn encode(height_map: &HeightMap, format: Format) -> Result<Vec<u8>, HeightMapIoError> {
+    match format {
+        Format::Pgm(depth) => {
+            let samples = samples(height_map, depth);
+            // 16 bit samples are only read as such when the maximum value exceeds 255
+            let min_max_value = match depth {
+                BitDepth::Eight => 1,
+                BitDepth::Sixteen => u8::MAX as usize + 1,
+            };
+            let max_value = usize::max(
+                samples.iter().copied().max().unwrap_or(0) as usize,
+                min_max_value,
+            );
+            let mut out = format!(
+                "P5\n{} {}\n{}\n",
+                height_map.cols(),
+                height_map.rows(),
+                max_value
+            )
+            .into_bytes();
+            out.extend(to_bytes(&samples, depth, true));
+            Ok(out)
+        }
+        Format::Png(depth) => {
+            let samples = samples(height_map, depth);
+            let mut out = Vec::new();
+            {
+                let mut encoder =
+                    png::Encoder::new(&mut out, height_map.cols() as u32, height_map.rows() as u32);
+                encoder.set_color(png::ColorType::Grayscale);
+                encoder.set_depth(match depth {
+                    BitDepth::Eight => png::BitDepth::Eight,
+                    BitDepth::Sixteen => png::BitDepth::Sixteen,
+                });
+                let mut writer = encoder
+                    .write_header()
+                    .map_err(|error| HeightMapIoError::Png(error.to_string()))?;
+                writer
+                    .write_image_data(&to_bytes(&samples, depth, true))
+                    .map_err(|error| HeightMapIoError::Png(error.to_string()))?;
+            }
+            Ok(out)
+        }
+        Format::RawU16 => Ok(to_bytes(
+            &samples(height_map, BitDepth::Sixteen),
+            BitDepth::Sixteen,
+            false,
+        )),
+    }
+}
+
+/// Decodes a PGM (binary or ASCII) or PNG image into a height map. Each sample becomes the elevation of its tile.
+/// Color PNGs are converted by averaging their channels, alpha is ignored.
+pub fn decode(bytes: &[u8]) -> Result<HeightMap, HeightMapIoError> {
+    if bytes.starts_with(b"P5") || bytes.starts_with(b"P2") {
+        decode_pgm(bytes)
+    } else if bytes.starts_with(&[0x89, b'P', b'N', b'G']) {
+        decode_png(bytes)
+    } else {
+        Err(HeightMapIoError::InvalidData(
+            "unknown image format, expected PGM or PNG".to_string(),
+        ))
+    }
+}
+
+/// Decodes raw little-endian u16 samples into a height map of the given dimension.
+pub fn decode_raw_u16(bytes: &[u8], dimension: &Dimension) -> Result<HeightMap, HeightMapIoError> {
+    let expected = dimension.width * dimension.height * 2;
+    if bytes.len() != expected {
+        return Err(HeightMapIoError::InvalidData(format!(
+            "expected {} bytes for a {}x{} map, found {}",
+            expected,
+            dimension.width,
+            dimension.height,
+            bytes.len()
+        )));
+    }
+    let samples = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]) as usize)
+        .collect::<Vec<usize>>();
+    Ok(from_samples(&samples, dimension.width))
+}
+
+/// Writes the height map to a file in the given format.
+pub fn save<P: AsRef<Path>>(
+    height_map: &HeightMap,
+    path: P,
+    format: Format,
+) -> Result<(), HeightMapIoError> {
+    fs::write(path, encode(height_map, format)?)?;
+    Ok(())
+}
+
+/// Reads a height map from a PGM or PNG file.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<HeightMap, HeightMapIoError> {
+    decode(&fs::read(path)?)
+}
+
+/// Reads a height map of the given dimension from a file of raw little-endian u16 samples.
+pub fn load_raw_u16<P: AsRef<Path>>(
+    path: P,
+    dimension: &Dimension,
+) -> Result<HeightMap, HeightMapIoError> {
+    decode_raw_u16(&fs::read(path)?, dimension)
+}
+
+/// Returns the samples of the map, row after row, scaled down if the highest elevation does not fit the bit depth.
+fn samples(height_map: &HeightMap, depth: BitDepth) -> Vec<u16> {
+    let max_elevation = (0..height_map.rows())
+        .flat_map(|i| (0..height_map.cols()).map(move |j| height_map.elevation(i, j)))
+        .max()
+        .unwrap_or(0);
+    let max_value = depth.max_value();
+    let mut out = Vec::with_capacity(height_map.rows() * height_map.cols());
+    for i in 0..height_map.rows() {
+        for j in 0..height_map.cols() {
+            let elevation = height_map.elevation(i, j);
+            let sample = if max_elevation > max_value {
+                (elevation as f64 * max_value as f64 / max_elevation as f64).round() as usize
+            } else {
+                elevation
+            };
+            out.push(sample as u16);
+        }
+    }
+    out
+}
+
+fn to_bytes(samples: &[u16], depth: BitDepth, big_endian: bool) -> Vec<u8> {
+    match depth {
+        BitDepth::Eight => samples.iter().map(|&sample| sample as u8).collect(),
+        BitDepth::Sixteen => samples
+            .iter()
+            .flat_map(|&sample| {
+                if big_endian {
+                    sample.to_be_bytes()
+                } else {
+                    sample.to_le_bytes()
+                }
+            })
+            .collect(),
+    }
+}
+
+fn from_samples(samples: &[usize], width: usize) -> HeightMap {
+    if width == 0 {
+        return HeightMap::from_elevations(&[]);
+    }
+    let rows = samples
+        .chunks(width)
+        .map(|row| row.to_vec())
+        .collect::<Vec<Vec<usize>>>();
+    HeightMap::from_elevations(&rows)
+}
+
+fn decode_pgm(bytes: &[u8]) -> Result<HeightMap, HeightMapIoError> {
+    let invalid = |message: &str| HeightMapIoError::InvalidData(format!("pgm: {}", message));
+    let ascii = bytes.starts_with(b"P2");
+
+    // Reads the magic number, width, height and maximum value, skipping comments
+    let mut header = Vec::new();
+    let mut position = 0;
+    while header.len() < 4 {
+        while position < bytes.len() && bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if position < bytes.len() && bytes[position] == b'#' {
+            while position < bytes.len() && bytes[position] != b'\n' {
+                position += 1;
+            }
+            continue;
+        }
+        let start = position;
+        while position < bytes.len() && !bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if start == position {
+            return Err(invalid("truncated header"));
+        }
+        header.push(String::from_utf8_lossy(&bytes[start..position]).to_string());
+    }
+    let parse = |value: &str| {
+        value
+            .parse::<usize>()
+            .map_err(|_| invalid(&format!("invalid header value {}", value)))
+    };
+    let width = parse(&header[1])?;
+    let height = parse(&header[2])?;
+    let max_value = parse(&header[3])?;
+    if max_value == 0 || max_value > u16::MAX as usize {
+        return Err(invalid("maximum value must be between 1 and 65535"));
+    }
+    let size = width
+        .checked_mul(height)
+        .ok_or_else(|| invalid(&format!("size {}x{} is too large", width, height)))?;
+
+    let samples = if ascii {
+        String::from_utf8_lossy(&bytes[position..])
+            .split_ascii_whitespace()
+            .map(|value| {
+                value
+                    .parse::<usize>()
+                    .map_err(|_| invalid(&format!("invalid sample {}", value)))
+            })
+            .collect::<Result<Vec<usize>, HeightMapIoError>>()?
+    } else {
+        // A single whitespace separates the header from the data
+        let data = &bytes[usize::min(position + 1, bytes.len())..];
+        if max_value > u8::MAX as usize {
+            data.chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]) as usize)
+                .collect()
+        } else {
+            data.iter().map(|&sample| sample as usize).collect()
+        }
+    };
+    if samples.len() < size {
+        return Err(invalid(&format!(
+            "expected {} samples, found {}",
+            size,
+            samples.len()
+        )));
+    }
+    Ok(from_samples(&samples[..size], width))
+}
+
+fn decode_png(bytes: &[u8]) -> Result<HeightMap, HeightMapIoError> {
+    let png_error = |error: png::DecodingError| HeightMapIoError::Png(error.to_string());
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(png_error)?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(png_error)?;
+
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        png::ColorType::Indexed => {
+            return Err(HeightMapIoError::InvalidData(
+                "png: unexpanded indexed colors".to_string(),
+            ))
+        }
+    };
+    // Alpha is not part of the color
+    let color_channels = if channels % 2 == 0 {
+        channels - 1
+    } else {
+        channels
+    };
+    let bytes_per_sample = match info.bit_depth {
+        png::BitDepth::Sixteen => 2,
+        _ => 1,
+    };
+    let width = info.width as usize;
+    let mut samples = Vec::with_capacity(width * info.height as usize);
+    for line in buffer[..info.buffer_size()].chunks(info.line_size) {
+        for pixel in line.chunks_exact(channels * bytes_per_sample).take(width) {
+            let sum = pixel
+                .chunks_exact(bytes_per_sample)
+                .take(color_channels)
+                .map(|sample| match bytes_per_sample {
+                    2 => u16::from_be_bytes([sample[0], sample[1]]) as usize,
+                    _ => sample[0] as usize,
+                })
+                .sum::<usize>();
+            samples.push((sum as f64 / color_channels as f64).round() as usize);
+        }
+    }
+    Ok(from_samples(&samples, width))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_map(max: usize) -> HeightMap {
+        let elevations: Vec<Vec<usize>> = (0..4)
+            .map(|i| (0..7).map(|j| (i * 7 + j) * max / 27).collect())
+            .collect();
+        HeightMap::from_elevations(&elevations)
+    }
+
+    fn assert_same(a: &HeightMap, b: &HeightMap) {
+        assert_eq!((a.rows(), a.cols()), (b.rows(), b.cols()));
+        assert_eq!(a.elevations(), b.elevations());
+    }
+
+    #[test]
+    fn images_round_trip() {
+        for (format, max) in [
+            (Format::Pgm(BitDepth::Eight), 200),
+            (Format::Pgm(BitDepth::Sixteen), 60000),
+            (Format::Png(BitDepth::Eight), 200),
+            (Format::Png(BitDepth::Sixteen), 60000),
+        ] {
+            let height_map = sample_map(max);
+            let decoded = decode(&encode(&height_map, format).unwrap()).unwrap();
+            assert_same(&height_map, &decoded);
+        }
+    }
+
+    #[test]
+    fn raw_round_trips() {
+        let height_map = sample_map(60000);
+        let bytes = encode(&height_map, Format::RawU16).unwrap();
+        let dimension = Dimension {
+            width: 7,
+            height: 4,
+        };
+        assert_same(&height_map, &decode_raw_u16(&bytes, &dimension).unwrap());
+        assert!(decode_raw_u16(&bytes[1..], &dimension).is_err());
+    }
+
+    #[test]
+    fn ascii_pgm_is_decoded() {
+        let height_map = decode(b"P2\n# comment\n3 2\n9\n0 1 2\n3 4 9\n").unwrap();
+        assert_same(
+            &height_map,
+            &HeightMap::from_elevations(&[vec![0, 1, 2], vec![3, 4, 9]]),
+        );
+    }
+
+    #[test]
+    fn malformed_pgm_is_refused() {
+        let huge = format!("P2\n{} 2\n9\n0\n", usize::MAX);
+        assert!(matches!(
+            decode(huge.as_bytes()),
+            Err(HeightMapIoError::InvalidData(message)) if message.contains("too large")
+        ));
+        assert!(matches!(
+            decode(b"P2\n2 1\n9\n0 x\n"),
+            Err(HeightMapIoError::InvalidData(message)) if message.contains("invalid sample x")
+        ));
+    }
+
+    #[test]
+    fn elevations_too_high_are_scaled_to_the_bit_depth() {
+        let height_map = sample_map(1000);
+        let decoded = decode(&encode(&height_map, Format::Png(BitDepth::Eight)).unwrap()).unwrap();
+        assert_eq!(decoded.elevations().iter().max(), Some(&255));
+    }
+
+    #[test]
+    fn unknown_format_is_refused() {
+        assert!(matches!(
+            decode(b"GIF89a"),
+            Err(HeightMapIoError::InvalidData(_))
+        ));
+    }
+}
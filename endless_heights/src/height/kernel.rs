@@ -0,0 +1,501 @@
+use rand::rngs::StdRng;
+use rand::Rng;
+
+use super::{Gaussian, GAUSSIAN_CUTOFF};
+use crate::utils::Dimension;
+
+/// A shape stamped onto the height map, such as a gaussian.
+/// Kernels are combined following a BlendMode, like gaussians.
+pub trait TerrainKernel: Send + Sync {
+    /// Returns the value of the kernel at coordinate x, y.
+    fn value_at(&self, x: f32, y: f32) -> f32;
+
+    /// Returns the range of y on row x outside of which the kernel stays below GAUSSIAN_CUTOFF, if the kernel reaches the row.
+    fn span_at(&self, x: f32) -> Option<(f32, f32)>;
+}
+
+/// The kinds of kernels the generator draws from a KernelMix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KernelKind {
+    Gaussian,
+    Volcano,
+    Crater,
+    Plateau,
+    Mesa,
+    TerracedHill,
+}
+
+/// Relative weights of each kind of kernel drawn by the generator.
+/// Weights must not be negative, and at least one must be positive. The default only draws gaussians.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KernelMix {
+    pub gaussian: f32,
+    pub volcano: f32,
+    pub crater: f32,
+    pub plateau: f32,
+    pub mesa: f32,
+    pub terraced_hill: f32,
+}
+
+impl Default for KernelMix {
+    fn default() -> Self {
+        KernelMix::only(KernelKind::Gaussian)
+    }
+}
+
+impl KernelMix {
+    /// A mix drawing a single kind of kernel.
+    pub fn only(kind: KernelKind) -> KernelMix {
+        let mut mix = KernelMix {
+            gaussian: 0.0,
+            volcano: 0.0,
+            crater: 0.0,
+            plateau: 0.0,
+            mesa: 0.0,
+            terraced_hill: 0.0,
+        };
+        *mix.weight_mut(kind) = 1.0;
+        mix
+    }
+
+    /// Returns the weight of a kind of kernel.
+    pub fn weight(&self, kind: KernelKind) -> f32 {
+        match kind {
+            KernelKind::Gaussian => self.gaussian,
+            KernelKind::Volcano => self.volcano,
+            KernelKind::Crater => self.crater,
+            KernelKind::Plateau => self.plateau,
+            KernelKind::Mesa => self.mesa,
+            KernelKind::TerracedHill => self.terraced_hill,
+        }
+    }
+
+    /// Returns the weight of a kind of kernel, to be changed.
+    pub fn weight_mut(&mut self, kind: KernelKind) -> &mut f32 {
+        match kind {
+            KernelKind::Gaussian => &mut self.gaussian,
+            KernelKind::Volcano => &mut self.volcano,
+            KernelKind::Crater => &mut self.crater,
+            KernelKind::Plateau => &mut self.plateau,
+            KernelKind::Mesa => &mut self.mesa,
+            KernelKind::TerracedHill => &mut self.terraced_hill,
+        }
+    }
+
+    /// Returns why the mix is invalid, if it is.
+    pub fn check(&self) -> Result<(), String> {
+        for kind in KINDS {
+            let weight = self.weight(kind);
+            if !weight.is_finite() || weight < 0.0 {
+                return Err(format!(
+                    "weight {} of {:?} is not a finite non-negative number",
+                    weight, kind
+                ));
+            }
+        }
+        if KINDS.iter().all(|kind| self.weight(*kind) == 0.0) {
+            return Err("every weight is 0".to_string());
+        }
+        Ok(())
+    }
+
+    /// Draws a kind of kernel. A mix of gaussians only does not draw from the rng.
+    pub(crate) fn pick(&self, rng: &mut StdRng) -> KernelKind {
+        if KINDS[1..].iter().all(|kind| self.weight(*kind) == 0.0) {
+            return KernelKind::Gaussian;
+        }
+        let total = KINDS.iter().map(|kind| self.weight(*kind)).sum::<f32>();
+        let mut draw = rng.gen_range(0.0..total);
+        for kind in KINDS {
+            draw -= self.weight(kind);
+            if draw < 0.0 {
+                return kind;
+            }
+        }
+        // Rounding can leave a tiny remainder
+        KINDS
+            .into_iter()
+            .rev()
+            .find(|kind| self.weight(*kind) > 0.0)
+            .unwrap_or(KernelKind::Gaussian)
+    }
+}
+
+const KINDS: [KernelKind; 6] = [
+    KernelKind::Gaussian,
+    KernelKind::Volcano,
+    KernelKind::Crater,
+    KernelKind::Plateau,
+    KernelKind::Mesa,
+    KernelKind::TerracedHill,
+];
+
+impl TerrainKernel for Gaussian {
+    fn value_at(&self, x: f32, y: f32) -> f32 {
+        self.get_value_at(x, y)
+    }
+
+    fn span_at(&self, x: f32) -> Option<(f32, f32)> {
+        let dx = x - self.mean_x;
+        if dx.abs() > self.extent_x {
+            return None;
+        }
+        // Roots of c dy² + 2b dx dy + (a dx² - cutoff) = 0
+        let discriminant = self.b * self.b * dx * dx - self.c * (self.a * dx * dx - self.cutoff);
+        if discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        Some((
+            self.mean_y + (-self.b * dx - root) / self.c,
+            self.mean_y + (-self.b * dx + root) / self.c,
+        ))
+    }
+}
+
+/// A cone with a caldera at its summit.
+/// - radius: Distance from the center where the cone meets the ground.
+/// - height: Elevation of the caldera rim.
+/// - caldera_radius: Radius of the caldera, smaller than radius.
+/// - caldera_depth: Depth of the caldera below its rim.
+#[derive(Clone, Debug)]
+pub struct Volcano {
+    pub mean_x: f32,
+    pub mean_y: f32,
+    pub radius: f32,
+    pub height: f32,
+    pub caldera_radius: f32,
+    pub caldera_depth: f32,
+}
+
+impl TerrainKernel for Volcano {
+    fn value_at(&self, x: f32, y: f32) -> f32 {
+        let r = f32::hypot(x - self.mean_x, y - self.mean_y);
+        if r >= self.radius {
+            0.0
+        } else if r <= self.caldera_radius {
+            let t = r / self.caldera_radius;
+            self.height - self.caldera_depth * (1.0 - t * t)
+        } else {
+            // Concave flanks, steep near the summit and spreading at the foot
+            let t = (self.radius - r) / (self.radius - self.caldera_radius);
+            self.height * t * t
+        }
+    }
+
+    fn span_at(&self, x: f32) -> Option<(f32, f32)> {
+        circle_span(self.mean_x, self.mean_y, self.radius, x)
+    }
+}
+
+/// An impact crater: a bowl ringed by a raised rim.
+/// - radius: Distance from the center to the crest of the rim.
+/// - rim_height: Elevation of the crest of the rim.
+/// - floor_height: Elevation of the center of the bowl.
+/// - rim_width: Standard deviation of the outer slope of the rim.
+#[derive(Clone, Debug)]
+pub struct Crater {
+    pub mean_x: f32,
+    pub mean_y: f32,
+    pub radius: f32,
+    pub rim_height: f32,
+    pub floor_height: f32,
+    pub rim_width: f32,
+}
+
+impl Crater {
+    /// Distance from the rim crest beyond which the outer slope is below GAUSSIAN_CUTOFF.
+    fn reach(&self) -> f32 {
+        if self.rim_height <= GAUSSIAN_CUTOFF {
+            return 0.0;
+        }
+        self.rim_width * f32::sqrt(2.0 * f32::ln(self.rim_height / GAUSSIAN_CUTOFF))
+    }
+}
+
+impl TerrainKernel for Crater {
+    fn value_at(&self, x: f32, y: f32) -> f32 {
+        let r = f32::hypot(x - self.mean_x, y - self.mean_y);
+        if r <= self.radius {
+            let t = r / self.radius;
+            self.floor_height + (self.rim_height - self.floor_height) * t.powi(4)
+        } else {
+            let d = (r - self.radius) / self.rim_width;
+            self.rim_height * f32::exp(-0.5 * d * d)
+        }
+    }
+
+    fn span_at(&self, x: f32) -> Option<(f32, f32)> {
+        circle_span(self.mean_x, self.mean_y, self.radius + self.reach(), x)
+    }
+}
+
+/// A flat-topped elliptical plateau surrounded by cliffs. With narrow cliffs, it makes a mesa.
+/// - radius_x, radius_y: Radii of the flat top before rotation.
+/// - angle: Rotation of the ellipse.
+/// - height: Elevation of the flat top.
+/// - cliff: Width of the cliffs, as a fraction of the radii. A width of 0 or below makes sheer cliffs.
+#[derive(Clone, Debug)]
+pub struct Plateau {
+    pub mean_x: f32,
+    pub mean_y: f32,
+    pub radius_x: f32,
+    pub radius_y: f32,
+    pub angle: f32,
+    pub height: f32,
+    pub cliff: f32,
+}
+
+impl TerrainKernel for Plateau {
+    fn value_at(&self, x: f32, y: f32) -> f32 {
+        let (dx, dy) = (x - self.mean_x, y - self.mean_y);
+        let (sin, cos) = self.angle.sin_cos();
+        let u = (dx * cos + dy * sin) / self.radius_x;
+        let v = (dy * cos - dx * sin) / self.radius_y;
+        let r = f32::hypot(u, v);
+        if self.cliff <= 0.0 {
+            return if r <= 1.0 { self.height } else { 0.0 };
+        }
+        self.height * (1.0 - smoothstep((r - 1.0) / self.cliff))
+    }
+
+    fn span_at(&self, x: f32) -> Option<(f32, f32)> {
+        let radius = f32::max(self.radius_x, self.radius_y) * (1.0 + self.cliff.max(0.0));
+        circle_span(self.mean_x, self.mean_y, radius, x)
+    }
+}
+
+/// A gaussian hill cut into flat steps joined by short risers.
+#[derive(Clone)]
+pub struct TerracedHill {
+    pub gaussian: Gaussian,
+    pub steps: usize,
+}
+
+impl TerrainKernel for TerracedHill {
+    fn value_at(&self, x: f32, y: f32) -> f32 {
+        let steps = self.steps as f32;
+        let t = self.gaussian.get_value_at(x, y) / self.gaussian.scale * steps;
+        // The last quarter of each step rises to the next one
+        let riser = smoothstep((t.fract() - 0.75) / 0.25);
+        (t.floor() + riser) / steps * self.gaussian.scale
+    }
+
+    fn span_at(&self, x: f32) -> Option<(f32, f32)> {
+        self.gaussian.span_at(x)
+    }
+}
+
+/// Draws a kernel of the given kind somewhere on the map.
+/// Its position, size and height are drawn like the ones of a gaussian, so that every kind has about the same footprint.
+pub(crate) fn sample_kernel(
+    rng: &mut StdRng,
+    kind: KernelKind,
+    dimension: &Dimension,
+    scale: f32,
+    min_variance: f32,
+    max_variance: f32,
+) -> Box<dyn TerrainKernel> {
+    // x runs along the rows of the map, y along the columns
+    let footprint = super::sample_footprint(
+        rng,
+        scale,
+        dimension.height as f32,
+        dimension.width as f32,
+        min_variance,
+        max_variance,
+    );
+    let gaussian = || {
+        Gaussian::new(
+            footprint.angle,
+            footprint.sigma_y,
+            footprint.sigma_x,
+            footprint.mean_y,
+            footprint.mean_x,
+            footprint.scale,
+        )
+    };
+    let (mean_x, mean_y) = (footprint.mean_x, footprint.mean_y);
+    let spread = 0.5 * (footprint.sigma_x + footprint.sigma_y);
+    let height = footprint.scale;
+    match kind {
+        KernelKind::Gaussian => Box::new(gaussian()),
+        KernelKind::Volcano => {
+            let radius = 3.0 * spread;
+            Box::new(Volcano {
+                mean_x,
+                mean_y,
+                radius,
+                height,
+                caldera_radius: rng.gen_range(0.1..0.25) * radius,
+                caldera_depth: rng.gen_range(0.2..0.4) * height,
+            })
+        }
+        KernelKind::Crater => Box::new(Crater {
+            mean_x,
+            mean_y,
+            radius: 2.0 * spread,
+            rim_height: 0.4 * height,
+            floor_height: 0.05 * height,
+            rim_width: 0.5 * spread,
+        }),
+        KernelKind::Plateau | KernelKind::Mesa => {
+            let mesa = kind == KernelKind::Mesa;
+            let size = if mesa { 1.0 } else { 1.5 };
+            Box::new(Plateau {
+                mean_x,
+                mean_y,
+                radius_x: size * spread * rng.gen_range(0.6..1.4),
+                radius_y: size * spread * rng.gen_range(0.6..1.4),
+                angle: footprint.angle,
+                height: if mesa { 0.6 * height } else { 0.7 * height },
+                cliff: if mesa { 0.08 } else { 0.35 },
+            })
+        }
+        KernelKind::TerracedHill => Box::new(TerracedHill {
+            gaussian: gaussian(),
+            steps: rng.gen_range(3..=6),
+        }),
+    }
+}
+
+/// Returns the range of y on row x inside the circle of the given center and radius.
+fn circle_span(mean_x: f32, mean_y: f32, radius: f32, x: f32) -> Option<(f32, f32)> {
+    let dx = x - mean_x;
+    if dx.abs() > radius {
+        return None;
+    }
+    let half = f32::sqrt(radius * radius - dx * dx);
+    Some((mean_y - half, mean_y + half))
+}
+
+/// Smooth step from 0 below 0 to 1 above 1.
+fn smoothstep(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn plateau(cliff: f32) -> Plateau {
+        Plateau {
+            mean_x: 10.0,
+            mean_y: 10.0,
+            radius_x: 4.0,
+            radius_y: 4.0,
+            angle: 0.0,
+            height: 8.0,
+            cliff,
+        }
+    }
+
+    #[test]
+    fn plateau_without_cliff_is_a_hard_step() {
+        for cliff in [0.0, -1.0] {
+            let plateau = plateau(cliff);
+            assert_eq!(plateau.value_at(10.0, 14.0), 8.0);
+            assert_eq!(plateau.value_at(10.0, 14.5), 0.0);
+            assert_eq!(plateau.span_at(10.0), Some((6.0, 14.0)));
+        }
+    }
+
+    #[test]
+    fn plateau_cliff_falls_smoothly() {
+        let plateau = plateau(0.5);
+        assert_eq!(plateau.value_at(10.0, 12.0), 8.0);
+        // Halfway down the cliff
+        assert!((plateau.value_at(10.0, 15.0) - 4.0).abs() < 1e-4);
+        assert_eq!(plateau.value_at(10.0, 16.0), 0.0);
+    }
+
+    #[test]
+    fn volcano_caldera_sinks_below_its_rim() {
+        let volcano = Volcano {
+            mean_x: 10.0,
+            mean_y: 10.0,
+            radius: 8.0,
+            height: 10.0,
+            caldera_radius: 2.0,
+            caldera_depth: 3.0,
+        };
+        assert_eq!(volcano.value_at(10.0, 10.0), 7.0);
+        assert_eq!(volcano.value_at(10.0, 12.0), 10.0);
+        // Continuous on both sides of the rim
+        assert!((volcano.value_at(10.0, 11.999) - 10.0).abs() < 0.01);
+        assert!((volcano.value_at(10.0, 12.001) - 10.0).abs() < 0.01);
+        assert!(volcano.value_at(10.0, 15.0) < 10.0);
+        assert_eq!(volcano.value_at(10.0, 18.0), 0.0);
+    }
+
+    #[test]
+    fn crater_rim_rises_above_its_floor() {
+        let crater = Crater {
+            mean_x: 10.0,
+            mean_y: 10.0,
+            radius: 5.0,
+            rim_height: 6.0,
+            floor_height: 1.0,
+            rim_width: 2.0,
+        };
+        assert_eq!(crater.value_at(10.0, 10.0), 1.0);
+        assert_eq!(crater.value_at(10.0, 15.0), 6.0);
+        assert!(crater.value_at(10.0, 12.0) < 6.0);
+        assert!(crater.value_at(10.0, 17.0) < 6.0);
+    }
+
+    #[test]
+    fn terraced_hill_is_cut_into_flat_steps() {
+        let hill = TerracedHill {
+            gaussian: Gaussian::new(0.0, 5.0, 5.0, 10.0, 10.0, 12.0),
+            steps: 4,
+        };
+        let values = (0..400)
+            .map(|k| hill.value_at(10.0, 10.0 + 0.05 * k as f32))
+            .collect::<Vec<f32>>();
+        assert!(values.windows(2).all(|pair| pair[1] <= pair[0] + 1e-4));
+        // Most of the slope lies on one of the levels 0, 3, 6, 9 or 12
+        let on_steps = values
+            .iter()
+            .filter(|value| ((*value / 3.0).round() * 3.0 - *value).abs() < 1e-4)
+            .count();
+        assert!(on_steps > values.len() / 2, "{}", on_steps);
+    }
+
+    #[test]
+    fn mix_draws_only_weighted_kinds() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let untouched = rng.clone();
+        assert_eq!(KernelMix::default().pick(&mut rng), KernelKind::Gaussian);
+        // A mix of gaussians only does not draw from the rng
+        assert_eq!(rng.gen::<u64>(), untouched.clone().gen::<u64>());
+
+        let mut mix = KernelMix::only(KernelKind::Volcano);
+        mix.crater = 3.0;
+        let craters = (0..1000)
+            .map(|_| mix.pick(&mut rng))
+            .inspect(|kind| assert!(matches!(kind, KernelKind::Volcano | KernelKind::Crater)))
+            .filter(|kind| *kind == KernelKind::Crater)
+            .count();
+        assert!((650..850).contains(&craters), "{}", craters);
+    }
+
+    #[test]
+    fn mix_refuses_invalid_weights() {
+        assert!(KernelMix::default().check().is_ok());
+        for weight in [-1.0, f32::NAN, f32::INFINITY] {
+            let mix = KernelMix {
+                mesa: weight,
+                ..Default::default()
+            };
+            assert!(mix.check().is_err(), "{}", weight);
+        }
+        let mix = KernelMix {
+            gaussian: 0.0,
+            ..Default::default()
+        };
+        assert!(mix.check().is_err());
+    }
+}
@@ -1,64 +1,119 @@
-use crate::utils::*;
+/// Import and export of height maps as grayscale images and raw data.
+pub mod io;
+/// Shapes stamped onto the height map.
+pub mod kernel;
+/// Mountain ranges swept along a curve.
+pub mod ridge;
+
+use crate::height::kernel::{KernelMix, TerrainKernel};
+use crate::height::ridge::Ridge;
+use crate::source::HeightSource;
+use crate::utils::Dimension;
 use crate::World;
 use std::fmt::Display;
 
 use rand::rngs::StdRng;
 use rand::Rng;
-use rand::SeedableRng;
 use rand_distr::{Distribution, Uniform};
 
-/// Struct to link a position to a certain elevation.
-#[allow(dead_code)]
-struct ElevationTile {
-    pos: Position,
-    elevation: usize,
-}
+/// Gaussian values below this elevation are considered negligible and are not evaluated.
+pub const GAUSSIAN_CUTOFF: f32 = 0.01;
 
-/// A grid of ElevationTiles.
-pub struct HeightMap(Vec<Vec<ElevationTile>>);
+/// A grid of elevations, stored row after row in a single buffer.
+#[derive(Clone)]
+pub struct HeightMap {
+    rows: usize,
+    cols: usize,
+    elevations: Vec<usize>,
+}
 #[macro_export]
 macro_rules! height_map {
     ($elevation:expr; ($rows:expr, $cols:expr)) => {{
-        let mut temp_height_map = Vec::new();
-        for i in 0..$rows {
-            let mut next_row = Vec::new();
-            for j in 0..$cols {
-                next_row.push(ElevationTile {
-                    elevation: $elevation,
-                    pos: Position { x: i, y: j },
-                });
+        $crate::height::HeightMap::filled($rows, $cols, $elevation)
+    }};
+}
+
+impl HeightMap {
+    /// Creates a height map with every tile at the same elevation.
+    pub fn filled(rows: usize, cols: usize, elevation: usize) -> HeightMap {
+        HeightMap {
+            rows,
+            cols,
+            elevations: vec![elevation; rows * cols],
+        }
+    }
+
+    /// Creates a height map from rows of elevations. Rows shorter than the first one are padded with 0.
+    pub fn from_elevations(elevations: &[Vec<usize>]) -> HeightMap {
+        let rows = elevations.len();
+        let cols = elevations.first().map_or(0, |row| row.len());
+        let mut height_map = height_map!(0; (rows, cols));
+        for (i, row) in elevations.iter().enumerate() {
+            for (j, elevation) in row.iter().take(cols).enumerate() {
+                height_map.set_elevation(i, j, *elevation);
             }
-            temp_height_map.push(next_row);
         }
-        HeightMap(temp_height_map)
-    }};
+        height_map
+    }
+
+    /// Returns the number of rows of the map.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Returns the number of columns of the map.
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// Returns the elevation at the given row and column.
+    pub fn elevation(&self, row: usize, col: usize) -> usize {
+        self.elevations[row * self.cols + col]
+    }
+
+    /// Sets the elevation at the given row and column.
+    pub fn set_elevation(&mut self, row: usize, col: usize, elevation: usize) {
+        self.elevations[row * self.cols + col] = elevation;
+    }
+
+    /// Returns every elevation, row after row.
+    pub fn elevations(&self) -> &[usize] {
+        &self.elevations
+    }
 }
 
 impl Display for HeightMap {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut out = String::from("");
-        for row in &self.0 {
-            for tile in row {
-                out = Vec::from([out.clone(), tile.elevation.to_string()]).join(" ");
+        for row in self.elevations.chunks(usize::max(self.cols, 1)) {
+            for elevation in row {
+                write!(f, " {}", elevation)?;
             }
-            out.push_str("\n");
+            writeln!(f)?;
         }
-        write!(f, "{}", out)
+        Ok(())
     }
 }
 
 /// A gaussian function with given parameters.
-struct Gaussian {
-    angle: f32,
-    sigma_y: f32,
-    sigma_x: f32,
-    mean_y: f32,
-    mean_x: f32,
+/// The coefficients of the quadratic form are computed once, together with the bounding box outside which the gaussian stays below GAUSSIAN_CUTOFF.
+#[derive(Clone, Debug)]
+pub struct Gaussian {
+    a: f32,
+    b: f32,
+    c: f32,
+    pub(crate) mean_y: f32,
+    pub(crate) mean_x: f32,
     scale: f32,
+    /// Value of the quadratic form at the cutoff
+    cutoff: f32,
+    pub(crate) extent_x: f32,
+    pub(crate) extent_y: f32,
 }
 
 impl Gaussian {
-    fn new(
+    /// Creates a gaussian of the given height `scale`, centered on (mean_x, mean_y), with standard deviations sigma_x and sigma_y rotated by angle.
+    /// Both standard deviations must be positive.
+    pub(crate) fn new(
         angle: f32,
         sigma_y: f32,
         sigma_x: f32,
@@ -68,140 +123,511 @@ impl Gaussian {
     ) -> Gaussian {
         assert!(sigma_x > 0.0);
         assert!(sigma_y > 0.0);
+        let a = f32::powf(f32::cos(angle), 2.0) / (2.0 * f32::powf(sigma_x, 2.0))
+            + f32::powf(f32::sin(angle), 2.0) / (2.0 * f32::powf(sigma_y, 2.0));
+        let b = f32::sin(2.0 * angle) / (4.0 * f32::powf(sigma_x, 2.0))
+            - f32::sin(2.0 * angle) / (4.0 * f32::powf(sigma_y, 2.0));
+        let c = f32::powf(f32::sin(angle), 2.0) / (2.0 * f32::powf(sigma_x, 2.0))
+            + f32::powf(f32::cos(angle), 2.0) / (2.0 * f32::powf(sigma_y, 2.0));
+
+        // The gaussian is above the cutoff inside the ellipse a dx² + 2b dx dy + c dy² <= cutoff
+        let cutoff = if scale > GAUSSIAN_CUTOFF {
+            f32::ln(scale / GAUSSIAN_CUTOFF)
+        } else {
+            0.0
+        };
+        let determinant = a * c - b * b;
         Gaussian {
-            angle,
-            sigma_y,
-            sigma_x,
+            a,
+            b,
+            c,
             mean_y,
             mean_x,
             scale,
+            cutoff,
+            extent_x: f32::sqrt(cutoff * c / determinant),
+            extent_y: f32::sqrt(cutoff * a / determinant),
         }
     }
+
     /// Returns the value for coordinate x, y for the gaussian.
-    fn get_value_at(&self, x: f32, y: f32) -> f32 {
-        let a = f32::powf(f32::cos(self.angle), 2.0) / (2.0 * f32::powf(self.sigma_x, 2.0))
-            + f32::powf(f32::sin(self.angle), 2.0) / (2.0 * f32::powf(self.sigma_y, 2.0));
-        let b = f32::sin(2.0 * self.angle) / (4.0 * f32::powf(self.sigma_x, 2.0))
-            - f32::sin(2.0 * self.angle) / (4.0 * f32::powf(self.sigma_y, 2.0));
-        let c = f32::powf(f32::sin(self.angle), 2.0) / (2.0 * f32::powf(self.sigma_x, 2.0))
-            + f32::powf(f32::cos(self.angle), 2.0) / (2.0 * f32::powf(self.sigma_y, 2.0));
-        self.scale
-            * f32::exp(
-                -((a * f32::powf(x - self.mean_x, 2.0))
-                    + 2.0 * b * (x - self.mean_x) * (y - self.mean_y)
-                    + c * f32::powf(y - self.mean_y, 2.0)),
-            )
+    pub(crate) fn get_value_at(&self, x: f32, y: f32) -> f32 {
+        let dx = x - self.mean_x;
+        let dy = y - self.mean_y;
+        self.scale * f32::exp(-(self.a * dx * dx + 2.0 * self.b * dx * dy + self.c * dy * dy))
     }
 }
 impl Default for Gaussian {
     fn default() -> Self {
-        Self {
-            angle: 0.0,
-            sigma_y: 1.0,
-            sigma_x: 1.0,
-            mean_y: 0.0,
-            mean_x: 0.0,
-            scale: 1.0,
-        }
+        Gaussian::new(0.0, 1.0, 1.0, 0.0, 0.0, 1.0)
     }
 }
 
-/// Creates an array of different gaussians randomly sampled given the parameters.
-fn sample_gaussians(
-    gaussians: &mut Vec<Gaussian>,
-    bumpiness: usize,
+/// Samples a single gaussian whose mean lies between 0 and `rows` along x and between 0 and `cols` along y.
+pub(crate) fn sample_gaussian(
+    rng: &mut StdRng,
     scale: f32,
-    limit: usize,
+    rows: f32,
+    cols: f32,
     min_variance: f32,
     max_variance: f32,
-) {
-    let mut rng = StdRng::seed_from_u64(2);
-    for _ in 0..bumpiness {
-        let angle = std::f32::consts::PI * rng.gen_range(0.0..2.0);
-
-        let uniform_sigma = Uniform::<f32>::from(min_variance..max_variance);
-        let uniform_mean = Uniform::<f32>::from(0.0..limit as f32);
-        let mean_x = uniform_mean.sample(&mut rng);
-        let mean_y = uniform_mean.sample(&mut rng);
-        let sigma_x = uniform_sigma.sample(&mut rng);
-        let sigma_y = uniform_sigma.sample(&mut rng);
-        let mut sampled_scale: f32 = 1.0;
-        if scale > 1.0 {
-            let uniform_scale = Uniform::<f32>::from(0.9..scale);
-            sampled_scale = uniform_scale.sample(&mut rng);
+) -> Gaussian {
+    let footprint = sample_footprint(rng, scale, rows, cols, min_variance, max_variance);
+    Gaussian::new(
+        footprint.angle,
+        footprint.sigma_y,
+        footprint.sigma_x,
+        footprint.mean_y,
+        footprint.mean_x,
+        footprint.scale,
+    )
+}
+
+/// The parameters drawn for a gaussian, also used to size the other kernels.
+pub(crate) struct Footprint {
+    angle: f32,
+    sigma_y: f32,
+    sigma_x: f32,
+    mean_y: f32,
+    mean_x: f32,
+    scale: f32,
+}
+
+/// Draws the parameters of a gaussian whose mean lies between 0 and `rows` along x and between 0 and `cols` along y.
+fn sample_footprint(
+    rng: &mut StdRng,
+    scale: f32,
+    rows: f32,
+    cols: f32,
+    min_variance: f32,
+    max_variance: f32,
+) -> Footprint {
+    let angle = std::f32::consts::PI * rng.gen_range(0.0..2.0);
+
+    let uniform_sigma = Uniform::<f32>::from(min_variance..max_variance);
+    let uniform_mean_x = Uniform::<f32>::from(0.0..rows);
+    let uniform_mean_y = Uniform::<f32>::from(0.0..cols);
+    let mean_x = uniform_mean_x.sample(rng);
+    let mean_y = uniform_mean_y.sample(rng);
+    let sigma_x = uniform_sigma.sample(rng);
+    let sigma_y = uniform_sigma.sample(rng);
+    let mut sampled_scale: f32 = 1.0;
+    if scale > 1.0 {
+        let uniform_scale = Uniform::<f32>::from(0.9..scale);
+        sampled_scale = uniform_scale.sample(rng);
+    }
+
+    Footprint {
+        angle,
+        sigma_y,
+        sigma_x,
+        mean_y,
+        mean_x,
+        scale: sampled_scale,
+    }
+}
+
+/// Returns the farthest distance from its mean at which a gaussian sampled with these parameters can reach GAUSSIAN_CUTOFF.
+pub(crate) fn gaussian_reach(scale: f32, max_variance: f32) -> f32 {
+    let max_scale = f32::max(scale, 1.0);
+    if max_scale <= GAUSSIAN_CUTOFF {
+        return 0.0;
+    }
+    max_variance * f32::sqrt(2.0 * f32::ln(max_scale / GAUSSIAN_CUTOFF))
+}
+
+/// How overlapping gaussians are combined into one elevation.
+/// `max` is the highest gaussian value at a position and `others` are the values of the remaining gaussians.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum BlendMode {
+    /// Only the highest gaussian counts: `max`.
+    Max,
+    /// Every gaussian counts fully: `max + sum(others)`.
+    Sum,
+    /// The highest gaussian plus a fraction of the others: `max + interpolation * sum(others)`.
+    #[default]
+    Weighted,
+    /// A soft maximum that rounds the creases where mountains meet: `ln(sum(exp(sharpness * v))) / sharpness`.
+    /// Higher sharpness gets closer to Max. Must be positive.
+    SmoothMax { sharpness: f32 },
+    /// Each other gaussian raises the elevation by a factor: `max * product(1 + interpolation * v / max)`.
+    Multiplicative,
+}
+
+impl BlendMode {
+    /// Combines the values of the gaussians at a position. `interpolation` is the weight of secondary gaussians.
+    /// `zeros` is the amount of further gaussians whose value was culled as negligible, and counts as 0.
+    pub fn combine(&self, values: &[f32], zeros: usize, interpolation: f32) -> f32 {
+        let Some(max) = values.iter().copied().reduce(f32::max) else {
+            return match self {
+                BlendMode::SmoothMax { sharpness } if zeros > 0 => (zeros as f32).ln() / sharpness,
+                _ => 0.0,
+            };
+        };
+        match self {
+            BlendMode::Max => max,
+            BlendMode::Sum => values.iter().sum(),
+            // The others sum to the total minus the highest value
+            BlendMode::Weighted => max + interpolation * (values.iter().sum::<f32>() - max),
+            BlendMode::SmoothMax { sharpness } => {
+                // Shifting by max keeps the exponentials from overflowing
+                let sum = values
+                    .iter()
+                    .map(|v| f32::exp(sharpness * (v - max)))
+                    .sum::<f32>()
+                    + zeros as f32 * f32::exp(-sharpness * max);
+                max + sum.ln() / sharpness
+            }
+            BlendMode::Multiplicative => {
+                if max <= 0.0 {
+                    return max;
+                }
+                let product = values
+                    .iter()
+                    .map(|v| 1.0 + interpolation * v / max)
+                    .product::<f32>();
+                // The highest gaussian itself is part of the product
+                max * product / (1.0 + interpolation)
+            }
         }
+    }
+}
 
-        gaussians.push(Gaussian::new(
-            angle,
-            sigma_y,
-            sigma_x,
-            mean_y,
-            mean_x,
-            sampled_scale,
-        ));
+/// The terrain kernels sampled for a map, gaussians by default, and its mountain ranges, combined following a BlendMode.
+/// Each kernel counts as a single value, whatever its kind.
+/// Carving kernels, such as valleys, are subtracted from the combined value.
+pub struct GaussianMixture {
+    kernels: Vec<Box<dyn TerrainKernel>>,
+    carvers: Vec<Box<dyn TerrainKernel>>,
+    interpolation: f32,
+    blend: BlendMode,
+}
+
+impl GaussianMixture {
+    /// Draws `bumpiness` gaussians from sample_gaussian() using the given rng.
+    /// They are combined with BlendMode::Weighted, unless changed with `with_blend()`.
+    pub fn sample(
+        rng: &mut StdRng,
+        dimension: &Dimension,
+        bumpiness: usize,
+        scale: f32,
+        interpolation: f32,
+        min_variance: f32,
+        max_variance: f32,
+    ) -> GaussianMixture {
+        GaussianMixture::sample_mix(
+            rng,
+            dimension,
+            bumpiness,
+            &KernelMix::default(),
+            scale,
+            interpolation,
+            min_variance,
+            max_variance,
+        )
+    }
+
+    /// Draws `bumpiness` kernels, each of a kind drawn from `mix`, using the given rng.
+    /// Their footprints are drawn like the ones of gaussians (see `sample()`).
+    #[allow(clippy::too_many_arguments)]
+    pub fn sample_mix(
+        rng: &mut StdRng,
+        dimension: &Dimension,
+        bumpiness: usize,
+        mix: &KernelMix,
+        scale: f32,
+        interpolation: f32,
+        min_variance: f32,
+        max_variance: f32,
+    ) -> GaussianMixture {
+        let kernels = (0..bumpiness)
+            .map(|_| {
+                let kind = mix.pick(rng);
+                kernel::sample_kernel(rng, kind, dimension, scale, min_variance, max_variance)
+            })
+            .collect();
+        GaussianMixture {
+            kernels,
+            carvers: Vec::new(),
+            interpolation,
+            blend: BlendMode::default(),
+        }
+    }
+
+    /// Sets how overlapping kernels are combined.
+    pub fn with_blend(mut self, blend: BlendMode) -> GaussianMixture {
+        self.blend = blend;
+        self
+    }
+
+    /// Adds mountain ranges alongside the kernels.
+    pub fn with_ridges(mut self, ridges: Vec<Ridge>) -> GaussianMixture {
+        self.kernels.extend(
+            ridges
+                .into_iter()
+                .map(|ridge| Box::new(ridge) as Box<dyn TerrainKernel>),
+        );
+        self
+    }
+
+    /// Adds kernels whose value is subtracted from the combined kernels, carving valleys and basins.
+    pub fn with_carvers(mut self, carvers: Vec<Box<dyn TerrainKernel>>) -> GaussianMixture {
+        self.carvers.extend(carvers);
+        self
+    }
+
+    /// Adds kernels alongside the sampled ones.
+    pub fn with_kernels(mut self, kernels: Vec<Box<dyn TerrainKernel>>) -> GaussianMixture {
+        self.kernels.extend(kernels);
+        self
     }
 }
 
-/// Creates a square map of elevation tiles.
-/// First an array of gaussians is drawn from sample_gaussians(). Given the set of different functions, each position is given an elevation based on the highest value amongst gaussians, plus a fraction of the other gaussians given 'interpolation'.
-#[allow(unused_assignments)]
+impl HeightSource for GaussianMixture {
+    fn height_at(&self, x: f32, y: f32) -> f32 {
+        let mut values = Vec::with_capacity(self.kernels.len());
+        for kernel in &self.kernels {
+            if kernel
+                .span_at(x)
+                .is_some_and(|(start, end)| start <= y && y <= end)
+            {
+                values.push(kernel.value_at(x, y));
+            }
+        }
+        let carved = self
+            .carvers
+            .iter()
+            .filter(|carver| {
+                carver
+                    .span_at(x)
+                    .is_some_and(|(start, end)| start <= y && y <= end)
+            })
+            .map(|carver| carver.value_at(x, y))
+            .sum::<f32>();
+        self.blend.combine(
+            &values,
+            self.kernels.len() - values.len(),
+            self.interpolation,
+        ) - carved
+    }
+
+    /// Only the kernels whose span crosses the row are evaluated, and only over their span.
+    fn fill_row(&self, x: f32, out: &mut [f32]) {
+        let spans = self
+            .kernels
+            .iter()
+            .filter_map(|kernel| kernel.span_at(x).map(|span| (kernel, span)))
+            .collect::<Vec<(&Box<dyn TerrainKernel>, (f32, f32))>>();
+        let carvers = self
+            .carvers
+            .iter()
+            .filter_map(|carver| carver.span_at(x).map(|span| (carver, span)))
+            .collect::<Vec<(&Box<dyn TerrainKernel>, (f32, f32))>>();
+        let mut values = Vec::with_capacity(spans.len());
+        for (j, elevation) in out.iter_mut().enumerate() {
+            let y = j as f32;
+            values.clear();
+            for (kernel, (start, end)) in &spans {
+                if *start <= y && y <= *end {
+                    values.push(kernel.value_at(x, y));
+                }
+            }
+            let carved = carvers
+                .iter()
+                .filter(|(_, (start, end))| *start <= y && y <= *end)
+                .map(|(carver, _)| carver.value_at(x, y))
+                .sum::<f32>();
+            *elevation = self.blend.combine(
+                &values,
+                self.kernels.len() - values.len(),
+                self.interpolation,
+            ) - carved;
+        }
+    }
+}
+
+/// Creates a map of elevation tiles with `dimension.height` rows and `dimension.width` columns.
+/// First an array of gaussians is drawn from GaussianMixture::sample() using the given rng. Given the set of different functions, each position is given an elevation based on the highest value amongst gaussians, plus a fraction of the other gaussians given 'interpolation' (see BlendMode::Weighted).
 pub fn create_height_map(
-    size: usize,
+    rng: &mut StdRng,
+    dimension: &Dimension,
     bumpiness: usize,
     scale: f32,
     interpolation: f32,
     min_variance: f32,
     max_variance: f32,
 ) -> HeightMap {
-    let mut gaussians = Vec::<Gaussian>::new();
-    sample_gaussians(
-        &mut gaussians,
+    create_height_map_with_ridges(
+        rng,
+        dimension,
         bumpiness,
+        0,
         scale,
-        size,
+        interpolation,
         min_variance,
         max_variance,
-    );
+    )
+}
 
-    let mut height_map = height_map!(0; (size, size));
-    let mut elevations = Vec::<usize>::new();
-    for i in 0..size {
-        for j in 0..size {
-            let mut elevation: usize = 0;
-            let mut gaussian_values = Vec::<usize>::new();
-            for gaussian in &gaussians {
-                gaussian_values.push(gaussian.get_value_at(i as f32, j as f32) as usize);
-            }
-            // Taking max value for each position
-            gaussian_values.sort();
-            elevation = gaussian_values[gaussian_values.len() - 1];
+/// Creates a map of elevation tiles like `create_height_map()`, adding `ridges` mountain ranges drawn from Ridge::sample() after the gaussians.
+/// Ranges count as one more value amongst the gaussians.
+#[allow(clippy::too_many_arguments)]
+pub fn create_height_map_with_ridges(
+    rng: &mut StdRng,
+    dimension: &Dimension,
+    bumpiness: usize,
+    ridges: usize,
+    scale: f32,
+    interpolation: f32,
+    min_variance: f32,
+    max_variance: f32,
+) -> HeightMap {
+    let mixture = GaussianMixture::sample(
+        rng,
+        dimension,
+        bumpiness,
+        scale,
+        interpolation,
+        min_variance,
+        max_variance,
+    );
+    let ridges = (0..ridges)
+        .map(|_| Ridge::sample(rng, dimension, scale, min_variance, max_variance))
+        .collect();
+    height_map_from_source(&mixture.with_ridges(ridges), dimension)
+}
 
-            // Adding some value of each other gaussian
-            for v in 0..gaussian_values.len() - 1 {
-                elevation += (interpolation * v as f32) as usize;
-            }
-            elevations.push(elevation);
-            let new_tile = ElevationTile {
-                pos: Position { x: i, y: j },
-                elevation,
-            };
-            height_map.0[i][j] = new_tile;
-        }
+/// Creates a map of elevation tiles by evaluating a height source at every tile.
+/// Negative values are clamped to 0, then the minimum elevation is subtracted from every tile.
+/// With the `parallel` feature, rows are evaluated on the rayon thread pool.
+pub fn height_map_from_source(source: &dyn HeightSource, dimension: &Dimension) -> HeightMap {
+    let mut height_map = height_map!(0; (dimension.height, dimension.width));
+    if dimension.width == 0 || dimension.height == 0 {
+        return height_map;
     }
-    elevations.sort();
-    let min_elevation = elevations[0];
-    for i in 0..size {
-        for j in 0..size {
-            height_map.0[i][j].elevation -= min_elevation;
+    let fill = |(i, row): (usize, &mut [usize])| {
+        let mut values = vec![0.0; row.len()];
+        source.fill_row(i as f32, &mut values);
+        for (elevation, value) in row.iter_mut().zip(values) {
+            *elevation = value.max(0.0) as usize;
         }
+    };
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        height_map
+            .elevations
+            .par_chunks_mut(dimension.width)
+            .enumerate()
+            .for_each(fill);
+    }
+    #[cfg(not(feature = "parallel"))]
+    height_map
+        .elevations
+        .chunks_mut(dimension.width)
+        .enumerate()
+        .for_each(fill);
+
+    let min_elevation = height_map.elevations.iter().copied().min().unwrap_or(0);
+    for elevation in height_map.elevations.iter_mut() {
+        *elevation -= min_elevation;
     }
     height_map
 }
 
 pub fn bump_world(world: &mut World, height_map: HeightMap) {
-    for i in 0..height_map.0.len() {
-        for j in 0..height_map.0.len() {
-            world[i][j].elevation = height_map.0[i][j].elevation;
+    for (world_row, row) in world
+        .iter_mut()
+        .zip(height_map.elevations.chunks(usize::max(height_map.cols, 1)))
+    {
+        for (tile, elevation) in world_row.iter_mut().zip(row) {
+            tile.elevation = *elevation;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    const DIMENSION: Dimension = Dimension {
+        width: 32,
+        height: 24,
+    };
+
+    #[test]
+    fn height_map_matches_the_unculled_nested_storage() {
+        // Recorded from the generator before rows were flattened and gaussian tails culled
+        let mut rng = StdRng::seed_from_u64(42);
+        let height_map = create_height_map(&mut rng, &DIMENSION, 12, 30.0, 0.1, 3.0, 8.0);
+        let elevations = height_map.elevations();
+        assert_eq!(elevations.iter().sum::<usize>(), 10362);
+        assert_eq!(elevations.iter().max(), Some(&29));
+        assert_eq!(&elevations[..8], &[6, 7, 8, 8, 9, 9, 10, 10]);
+        assert_eq!(
+            &elevations[12 * 32..12 * 32 + 8],
+            &[18, 20, 22, 24, 26, 27, 28, 28]
+        );
+    }
+
+    #[test]
+    fn culled_rows_match_every_kernel_evaluated() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mix = KernelMix {
+            gaussian: 1.0,
+            volcano: 1.0,
+            crater: 1.0,
+            plateau: 1.0,
+            mesa: 1.0,
+            terraced_hill: 1.0,
+        };
+        let ridge = Ridge::sample(&mut rng, &DIMENSION, 30.0, 3.0, 8.0);
+        let mixture =
+            GaussianMixture::sample_mix(&mut rng, &DIMENSION, 16, &mix, 30.0, 0.1, 3.0, 8.0)
+                .with_ridges(vec![ridge]);
+        // Each culled kernel is below the cutoff, and only weighs `interpolation` of it
+        let tolerance = 0.1 * GAUSSIAN_CUTOFF * mixture.kernels.len() as f32;
+        let mut row = vec![0.0; DIMENSION.width];
+        for i in 0..DIMENSION.height {
+            let x = i as f32;
+            mixture.fill_row(x, &mut row);
+            for (j, value) in row.iter().enumerate() {
+                let y = j as f32;
+                assert_eq!(*value, mixture.height_at(x, y));
+                let values = mixture
+                    .kernels
+                    .iter()
+                    .map(|kernel| kernel.value_at(x, y))
+                    .collect::<Vec<f32>>();
+                let unculled = mixture.blend.combine(&values, 0, mixture.interpolation);
+                assert!(
+                    (value - unculled).abs() <= tolerance,
+                    "({}, {}): {} against {}",
+                    i,
+                    j,
+                    value,
+                    unculled
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn blend_modes_combine_as_documented() {
+        let values = [4.0, 2.0];
+        assert_eq!(BlendMode::Max.combine(&values, 0, 0.5), 4.0);
+        assert_eq!(BlendMode::Sum.combine(&values, 0, 0.5), 6.0);
+        assert_eq!(BlendMode::Weighted.combine(&values, 0, 0.5), 5.0);
+        let multiplicative = BlendMode::Multiplicative.combine(&values, 0, 0.5);
+        assert!((multiplicative - 4.0 * (1.0 + 0.5 * 2.0 / 4.0)).abs() < 1e-5);
+
+        let sharpness = 2.0;
+        let smooth = BlendMode::SmoothMax { sharpness }.combine(&values, 0, 0.5);
+        assert!(smooth > 4.0 && smooth <= 4.0 + 2f32.ln() / sharpness);
+        // Kernels culled as zeros still count towards the soft maximum
+        let culled = BlendMode::SmoothMax { sharpness }.combine(&[], 3, 0.5);
+        assert!((culled - 3f32.ln() / sharpness).abs() < 1e-6);
+        assert_eq!(BlendMode::Max.combine(&[], 3, 0.5), 0.0);
+    }
+}
@@ -0,0 +1,251 @@
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand_distr::{Distribution, Uniform};
+
+use super::kernel::TerrainKernel;
+use super::GAUSSIAN_CUTOFF;
+use crate::builder::GeneratorConfigError;
+use crate::source::HeightSource;
+use crate::utils::Dimension;
+
+/// A mountain range: a gaussian cross-section swept along a spine.
+/// The spine is a curve of (x, y, height) points, and the height of the range follows the one of the spine, so that low points form passes.
+/// Across the spine, the elevation falls off as a gaussian with standard deviation `width`.
+#[derive(Clone, Debug)]
+pub struct Ridge {
+    /// Points of the spine, joined by straight segments
+    spine: Vec<(f32, f32, f32)>,
+    width: f32,
+    /// Farthest distance from the spine where the range is above GAUSSIAN_CUTOFF
+    reach: f32,
+    min_x: f32,
+    max_x: f32,
+    min_y: f32,
+    max_y: f32,
+}
+
+impl Ridge {
+    /// Creates a range whose spine joins the given (x, y, height) points with straight segments.
+    /// Returns an error if there are no points, if a point is not finite or if the width is not a positive number.
+    pub fn polyline(points: &[(f32, f32, f32)], width: f32) -> Result<Ridge, GeneratorConfigError> {
+        check(points, width)?;
+        Ok(Ridge::from_spine(points, width))
+    }
+
+    /// Creates a range whose spine is a Catmull-Rom spline through the given (x, y, height) points.
+    /// Returns an error if there are no points, if a point is not finite or if the width is not a positive number.
+    pub fn spline(points: &[(f32, f32, f32)], width: f32) -> Result<Ridge, GeneratorConfigError> {
+        check(points, width)?;
+        Ok(Ridge::from_spine(&spline(points), width))
+    }
+
+    /// Creates a range joining the points of its spine with straight segments. `points` must not be empty.
+    fn from_spine(points: &[(f32, f32, f32)], width: f32) -> Ridge {
+        let max_height = points
+            .iter()
+            .map(|(_, _, height)| *height)
+            .fold(0.0, f32::max);
+        let reach = if max_height > GAUSSIAN_CUTOFF {
+            width * f32::sqrt(2.0 * f32::ln(max_height / GAUSSIAN_CUTOFF))
+        } else {
+            0.0
+        };
+        let bound = |coordinate: fn(&(f32, f32, f32)) -> f32, pick: fn(f32, f32) -> f32| {
+            points.iter().map(coordinate).reduce(pick).unwrap_or(0.0)
+        };
+        let mut spine = points.to_vec();
+        if spine.len() == 1 {
+            // A single point is a segment of length 0
+            spine.push(points[0]);
+        }
+        Ridge {
+            spine,
+            width,
+            reach,
+            min_x: bound(|p| p.0, f32::min) - reach,
+            max_x: bound(|p| p.0, f32::max) + reach,
+            min_y: bound(|p| p.1, f32::min) - reach,
+            max_y: bound(|p| p.1, f32::max) + reach,
+        }
+    }
+
+    /// Samples a winding range centered on the map, about half to nine tenths of the longest side of the map long.
+    /// Its crest is drawn like the scale of a gaussian, tapers at both ends and dips into a pass now and then.
+    /// Its width is half a variance drawn between min_variance and max_variance.
+    pub fn sample(
+        rng: &mut StdRng,
+        dimension: &Dimension,
+        scale: f32,
+        min_variance: f32,
+        max_variance: f32,
+    ) -> Ridge {
+        const CONTROL_POINTS: usize = 6;
+        let rows = dimension.height as f32;
+        let cols = dimension.width as f32;
+        let length = rng.gen_range(0.5..0.9) * f32::max(rows, cols);
+        let step = length / (CONTROL_POINTS - 1) as f32;
+        let mut angle = std::f32::consts::PI * rng.gen_range(0.0..2.0);
+        let center_x = Uniform::<f32>::from(0.0..rows).sample(rng);
+        let center_y = Uniform::<f32>::from(0.0..cols).sample(rng);
+        let mut peak: f32 = 1.0;
+        if scale > 1.0 {
+            peak = Uniform::<f32>::from(0.9..scale).sample(rng);
+        }
+        let width = 0.5 * Uniform::<f32>::from(min_variance..max_variance).sample(rng);
+
+        let mut x = center_x - 0.5 * length * angle.cos();
+        let mut y = center_y - 0.5 * length * angle.sin();
+        let mut points = Vec::with_capacity(CONTROL_POINTS);
+        for i in 0..CONTROL_POINTS {
+            let height = if i == 0 || i == CONTROL_POINTS - 1 {
+                0.25 * peak
+            } else if rng.gen_bool(0.25) {
+                rng.gen_range(0.25..0.45) * peak
+            } else {
+                rng.gen_range(0.6..1.0) * peak
+            };
+            points.push((x, y, height));
+            angle += rng.gen_range(-0.5..0.5);
+            x += step * angle.cos();
+            y += step * angle.sin();
+        }
+        Ridge::from_spine(&spline(&points), width)
+    }
+}
+
+/// Returns the points of a Catmull-Rom spline through the given (x, y, height) points, which must not be empty.
+fn spline(points: &[(f32, f32, f32)]) -> Vec<(f32, f32, f32)> {
+    let last = points.len() - 1;
+    let mut spine = vec![points[0]];
+    for i in 0..last {
+        let p0 = points[i.saturating_sub(1)];
+        let p1 = points[i];
+        let p2 = points[i + 1];
+        let p3 = points[usize::min(i + 2, last)];
+        // About one spine point per tile
+        let steps = f32::hypot(p2.0 - p1.0, p2.1 - p1.1).ceil().max(1.0) as usize;
+        for step in 1..=steps {
+            let t = step as f32 / steps as f32;
+            let at = |c0: f32, c1: f32, c2: f32, c3: f32| {
+                0.5 * (2.0 * c1
+                    + (c2 - c0) * t
+                    + (2.0 * c0 - 5.0 * c1 + 4.0 * c2 - c3) * t * t
+                    + (3.0 * c1 - c0 - 3.0 * c2 + c3) * t * t * t)
+            };
+            spine.push((
+                at(p0.0, p1.0, p2.0, p3.0),
+                at(p0.1, p1.1, p2.1, p3.1),
+                at(p0.2, p1.2, p2.2, p3.2).max(0.0),
+            ));
+        }
+    }
+    spine
+}
+
+/// Returns an error if a range has no points, if a point is not finite or if its width is not a positive number.
+fn check(points: &[(f32, f32, f32)], width: f32) -> Result<(), GeneratorConfigError> {
+    if points.is_empty() {
+        return Err(GeneratorConfigError::InvalidRidge(
+            "the spine has no points".to_string(),
+        ));
+    }
+    if let Some(point) = points
+        .iter()
+        .find(|(x, y, height)| !x.is_finite() || !y.is_finite() || !height.is_finite())
+    {
+        return Err(GeneratorConfigError::InvalidRidge(format!(
+            "the spine point {:?} is not finite",
+            point
+        )));
+    }
+    // NaN is not finite, so it is refused too
+    if !width.is_finite() || width <= 0.0 {
+        return Err(GeneratorConfigError::InvalidRidge(format!(
+            "the width must be a positive number, found {}",
+            width
+        )));
+    }
+    Ok(())
+}
+
+impl TerrainKernel for Ridge {
+    /// Highest value of the cross-sections of every segment of the spine.
+    fn value_at(&self, x: f32, y: f32) -> f32 {
+        let reach_squared = self.reach * self.reach;
+        let mut value: f32 = 0.0;
+        for pair in self.spine.windows(2) {
+            let (start, end) = (pair[0], pair[1]);
+            let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+            let length_squared = dx * dx + dy * dy;
+            let t = if length_squared > 0.0 {
+                (((x - start.0) * dx + (y - start.1) * dy) / length_squared).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let (px, py) = (start.0 + t * dx - x, start.1 + t * dy - y);
+            let distance_squared = px * px + py * py;
+            if distance_squared > reach_squared {
+                continue;
+            }
+            let height = start.2 + t * (end.2 - start.2);
+            value =
+                value.max(height * f32::exp(-distance_squared / (2.0 * self.width * self.width)));
+        }
+        value
+    }
+
+    /// The bounding box of the spine, widened by the reach of the cross-section.
+    fn span_at(&self, x: f32) -> Option<(f32, f32)> {
+        if self.min_x <= x && x <= self.max_x {
+            Some((self.min_y, self.max_y))
+        } else {
+            None
+        }
+    }
+}
+
+impl HeightSource for Ridge {
+    fn height_at(&self, x: f32, y: f32) -> f32 {
+        self.value_at(x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_ranges_are_refused() {
+        let points = [(0.0, 0.0, 10.0), (0.0, 10.0, 10.0)];
+        for ridge in [
+            Ridge::polyline(&[], 2.0),
+            Ridge::spline(&[], 2.0),
+            Ridge::polyline(&points, 0.0),
+            Ridge::spline(&points, f32::NAN),
+            Ridge::polyline(&[(0.0, f32::INFINITY, 10.0)], 2.0),
+        ] {
+            assert!(matches!(ridge, Err(GeneratorConfigError::InvalidRidge(_))));
+        }
+    }
+
+    #[test]
+    fn crest_follows_the_spine() {
+        let ridge = Ridge::polyline(&[(5.0, 0.0, 10.0), (5.0, 10.0, 20.0)], 2.0).unwrap();
+        assert_eq!(ridge.value_at(5.0, 0.0), 10.0);
+        assert_eq!(ridge.value_at(5.0, 5.0), 15.0);
+        assert_eq!(ridge.value_at(5.0, 10.0), 20.0);
+        // One width away from the spine is exp(-1/2) of the crest
+        assert!((ridge.value_at(7.0, 5.0) - 15.0 * f32::exp(-0.5)).abs() < 1e-4);
+        assert_eq!(ridge.span_at(100.0), None);
+    }
+
+    #[test]
+    fn spline_goes_through_its_points() {
+        // A constant crest keeps the spline from overshooting the heights of its points
+        let points = [(0.0, 0.0, 10.0), (4.0, 6.0, 10.0), (2.0, 14.0, 10.0)];
+        let ridge = Ridge::spline(&points, 1.5).unwrap();
+        for (x, y, _) in points {
+            assert!((ridge.value_at(x, y) - 10.0).abs() < 1e-3);
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::height::HeightMap;
+use crate::World;
+use robotics_lib::world::tile::TileType;
+
+/// Parameters of the hydrology stage.
+/// - river_threshold: Amount of upstream tiles that must drain through a tile for it to become a river.
+/// - min_lake_size: Minimum amount of tiles a filled basin must cover to become a lake. Smaller basins stay dry.
+/// - deep_lake_depth: Lake tiles at least this deep below the water surface become DeepWater, the others ShallowWater.
+#[derive(Clone, Debug)]
+pub struct HydrologyParams {
+    pub river_threshold: usize,
+    pub min_lake_size: usize,
+    pub deep_lake_depth: usize,
+}
+
+impl Default for HydrologyParams {
+    fn default() -> Self {
+        Self {
+            river_threshold: 40,
+            min_lake_size: 4,
+            deep_lake_depth: 3,
+        }
+    }
+}
+
+impl HydrologyParams {
+    /// Returns why the parameters are invalid, if they are.
+    pub fn check(&self) -> Result<(), String> {
+        // Every tile drains at least itself
+        if self.river_threshold < 2 {
+            return Err(format!(
+                "river threshold {} turns every tile into a river, it must be at least 2",
+                self.river_threshold
+            ));
+        }
+        if self.min_lake_size == 0 {
+            return Err("the minimum lake size must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// The kind of water found on a tile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Water {
+    None,
+    River,
+    /// Lake with its depth below the water surface.
+    Lake(usize),
+}
+
+/// Result of the hydrology stage.
+/// - flow: For each tile, the tile it drains into. Tiles on the border drain out of the map and have no target.
+/// - accumulation: For each tile, the amount of tiles draining through it, itself included.
+/// - water: The rivers and lakes found on the map.
+pub struct Hydrology {
+    pub flow: Vec<Vec<Option<(usize, usize)>>>,
+    pub accumulation: Vec<Vec<usize>>,
+    pub water: Vec<Vec<Water>>,
+}
+
+/// Computes flow direction, flow accumulation, lakes and rivers of a height map.
+/// Basins are filled with a priority flood starting from the border of the map, which acts as the sea.
+/// Each tile drains into the neighbour it was flooded from, so flat lake surfaces still drain towards their outlet.
+pub fn compute(height_map: &HeightMap, params: &HydrologyParams) -> Hydrology {
+    let rows = height_map.rows();
+    let cols = height_map.cols();
+    let mut filled = vec![vec![0; cols]; rows];
+    let mut flow = vec![vec![None; cols]; rows];
+    let mut visited = vec![vec![false; cols]; rows];
+    let mut order = Vec::with_capacity(rows * cols);
+    // Ties are broken by insertion order, so flats are drained breadth first
+    let mut queue = BinaryHeap::new();
+    let mut counter: usize = 0;
+
+    for i in 0..rows {
+        for j in 0..cols {
+            if i == 0 || j == 0 || i == rows - 1 || j == cols - 1 {
+                visited[i][j] = true;
+                filled[i][j] = height_map.elevation(i, j);
+                queue.push(Reverse((filled[i][j], counter, i, j)));
+                counter += 1;
+            }
+        }
+    }
+
+    while let Some(Reverse((level, _, i, j))) = queue.pop() {
+        order.push((i, j));
+        for (ni, nj) in neighbours(i, j, rows, cols) {
+            if visited[ni][nj] {
+                continue;
+            }
+            visited[ni][nj] = true;
+            filled[ni][nj] = usize::max(level, height_map.elevation(ni, nj));
+            flow[ni][nj] = Some((i, j));
+            queue.push(Reverse((filled[ni][nj], counter, ni, nj)));
+            counter += 1;
+        }
+    }
+
+    // Upstream tiles are always popped after the tile they drain into
+    let mut accumulation = vec![vec![1; cols]; rows];
+    for &(i, j) in order.iter().rev() {
+        if let Some((ti, tj)) = flow[i][j] {
+            accumulation[ti][tj] += accumulation[i][j];
+        }
+    }
+
+    let mut water = vec![vec![Water::None; cols]; rows];
+    mark_lakes(height_map, &filled, params.min_lake_size, &mut water);
+    for i in 0..rows {
+        for j in 0..cols {
+            if water[i][j] == Water::None && accumulation[i][j] >= params.river_threshold {
+                water[i][j] = Water::River;
+            }
+        }
+    }
+
+    Hydrology {
+        flow,
+        accumulation,
+        water,
+    }
+}
+
+/// Turns the water found by compute() into ShallowWater and DeepWater tiles.
+/// Should run after the biome stage, otherwise the water is overwritten.
+pub fn apply(world: &mut World, hydrology: &Hydrology, params: &HydrologyParams) {
+    for (world_row, water_row) in world.iter_mut().zip(&hydrology.water) {
+        for (tile, water) in world_row.iter_mut().zip(water_row) {
+            match water {
+                Water::None => {}
+                Water::River => {
+                    if tile.tile_type != TileType::DeepWater {
+                        tile.tile_type = TileType::ShallowWater;
+                    }
+                }
+                Water::Lake(depth) => {
+                    tile.tile_type = if *depth >= params.deep_lake_depth {
+                        TileType::DeepWater
+                    } else {
+                        TileType::ShallowWater
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// Marks as lakes the connected groups of filled tiles covering at least min_lake_size tiles.
+fn mark_lakes(
+    height_map: &HeightMap,
+    filled: &[Vec<usize>],
+    min_lake_size: usize,
+    water: &mut [Vec<Water>],
+) {
+    let rows = height_map.rows();
+    let cols = height_map.cols();
+    let is_flooded = |i: usize, j: usize| filled[i][j] > height_map.elevation(i, j);
+    let mut seen = vec![vec![false; cols]; rows];
+    for i in 0..rows {
+        for j in 0..cols {
+            if seen[i][j] || !is_flooded(i, j) {
+                continue;
+            }
+            seen[i][j] = true;
+            let mut basin = vec![(i, j)];
+            let mut stack = vec![(i, j)];
+            while let Some((ci, cj)) = stack.pop() {
+                for (ni, nj) in neighbours(ci, cj, rows, cols) {
+                    if !seen[ni][nj] && is_flooded(ni, nj) {
+                        seen[ni][nj] = true;
+                        basin.push((ni, nj));
+                        stack.push((ni, nj));
+                    }
+                }
+            }
+            if basin.len() >= min_lake_size {
+                for (bi, bj) in basin {
+                    water[bi][bj] = Water::Lake(filled[bi][bj] - height_map.elevation(bi, bj));
+                }
+            }
+        }
+    }
+}
+
+/// Returns the 4-connected neighbours of a tile that lie inside the map.
+pub(crate) fn neighbours(
+    i: usize,
+    j: usize,
+    rows: usize,
+    cols: usize,
+) -> impl Iterator<Item = (usize, usize)> {
+    let mut out = Vec::with_capacity(4);
+    if i > 0 {
+        out.push((i - 1, j));
+    }
+    if i + 1 < rows {
+        out.push((i + 1, j));
+    }
+    if j > 0 {
+        out.push((i, j - 1));
+    }
+    if j + 1 < cols {
+        out.push((i, j + 1));
+    }
+    out.into_iter()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 5x5 bowl: a rim at 10 with an outlet at 6 on the first row, around a floor at 2.
+    fn bowl() -> HeightMap {
+        let mut elevations = vec![vec![10; 5]; 5];
+        elevations[0][2] = 6;
+        for row in elevations.iter_mut().take(4).skip(1) {
+            row[1..4].fill(2);
+        }
+        HeightMap::from_elevations(&elevations)
+    }
+
+    #[test]
+    fn basin_fills_up_to_its_outlet() {
+        let hydrology = compute(&bowl(), &HydrologyParams::default());
+        for i in 1..4 {
+            for j in 1..4 {
+                assert_eq!(hydrology.water[i][j], Water::Lake(4));
+            }
+        }
+        assert_eq!(hydrology.water[0][0], Water::None);
+    }
+
+    #[test]
+    fn small_basin_stays_dry() {
+        let params = HydrologyParams {
+            min_lake_size: 10,
+            ..Default::default()
+        };
+        let hydrology = compute(&bowl(), &params);
+        assert!(hydrology
+            .water
+            .iter()
+            .flatten()
+            .all(|w| *w != Water::Lake(4)));
+    }
+
+    #[test]
+    fn every_tile_drains_out_of_the_map() {
+        let (rows, cols) = (6, 9);
+        let elevations: Vec<Vec<usize>> = (0..rows)
+            .map(|i| (0..cols).map(|j| (i * 7 + j * 3) % 11).collect())
+            .collect();
+        let hydrology = compute(
+            &HeightMap::from_elevations(&elevations),
+            &HydrologyParams::default(),
+        );
+        let mut outflow = 0;
+        for i in 0..rows {
+            for j in 0..cols {
+                let (mut ci, mut cj) = (i, j);
+                let mut steps = 0;
+                while let Some(next) = hydrology.flow[ci][cj] {
+                    (ci, cj) = next;
+                    steps += 1;
+                    assert!(steps <= rows * cols, "flow loops from ({}, {})", i, j);
+                }
+                assert!(ci == 0 || cj == 0 || ci == rows - 1 || cj == cols - 1);
+                if hydrology.flow[i][j].is_none() {
+                    outflow += hydrology.accumulation[i][j];
+                }
+            }
+        }
+        assert_eq!(outflow, rows * cols);
+    }
+
+    #[test]
+    fn apply_turns_deep_lakes_into_deep_water() {
+        let height_map = bowl();
+        let params = HydrologyParams::default();
+        let hydrology = compute(&height_map, &params);
+        let mut world: World = (0..5)
+            .map(|_| {
+                (0..5)
+                    .map(|_| robotics_lib::world::tile::Tile {
+                        tile_type: TileType::Grass,
+                        content: robotics_lib::world::tile::Content::None,
+                        elevation: 0,
+                    })
+                    .collect()
+            })
+            .collect();
+        apply(&mut world, &hydrology, &params);
+        assert_eq!(world[2][2].tile_type, TileType::DeepWater);
+        assert_eq!(world[0][0].tile_type, TileType::Grass);
+    }
+}
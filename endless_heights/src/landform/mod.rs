@@ -0,0 +1,490 @@
+use std::collections::VecDeque;
+
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand_distr::{Distribution, Uniform};
+
+use crate::height::kernel::TerrainKernel;
+use crate::height::ridge::Ridge;
+use crate::height::{self, HeightMap};
+use crate::hydrology::neighbours;
+use crate::utils::Dimension;
+
+/// Parameters of the valleys, guaranteed mountains and passes.
+/// - min_mountains: Least amount of peaks whose prominence is above `prominence`. Missing mountains are raised where the map is the flattest.
+/// - prominence: Fraction between 0 and 1 of the elevation range a peak must rise above the highest saddle joining it to a higher peak to count as a mountain.
+/// - passes: Amount of passes cut through the saddles of the mountain ranges, each giving a gentle crossing from one side to the other.
+/// - valleys: Amount of valleys and basins carved into the terrain.
+#[derive(Clone, Debug)]
+pub struct LandformParams {
+    pub min_mountains: usize,
+    pub prominence: f32,
+    pub passes: usize,
+    pub valleys: usize,
+}
+
+impl Default for LandformParams {
+    fn default() -> Self {
+        Self {
+            min_mountains: 0,
+            prominence: 0.2,
+            passes: 0,
+            valleys: 0,
+        }
+    }
+}
+
+/// A summit of the height map.
+/// - prominence: Height of the summit above the highest saddle joining it to a higher summit, or above the lowest tile for the highest summit.
+/// - saddle: (row, column) of that saddle, None for the highest summit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Peak {
+    pub row: usize,
+    pub col: usize,
+    pub elevation: usize,
+    pub prominence: usize,
+    pub saddle: Option<(usize, usize)>,
+}
+
+/// Half width of the corridor cut by a pass, in tiles.
+const PASS_WIDTH: f32 = 3.0;
+/// Fraction of the elevation range above which tiles belong to a mountain range, for passes.
+const RANGE_LEVEL: f32 = 0.5;
+/// Amount of random tiles considered when looking for room for a missing mountain.
+const CANDIDATES: usize = 32;
+
+/// Samples `amount` valleys and basins, to be carved into the terrain by subtracting their value.
+/// Valleys are winding troughs, drawn like mountain ranges (see `Ridge::sample()`), and basins are round hollows drawn like gaussians.
+pub fn sample_valleys(
+    rng: &mut StdRng,
+    dimension: &Dimension,
+    amount: usize,
+    scale: f32,
+    min_variance: f32,
+    max_variance: f32,
+) -> Vec<Box<dyn TerrainKernel>> {
+    let depth = 0.6 * scale;
+    (0..amount)
+        .map(|_| -> Box<dyn TerrainKernel> {
+            if rng.gen_bool(0.7) {
+                Box::new(Ridge::sample(
+                    rng,
+                    dimension,
+                    depth,
+                    min_variance,
+                    max_variance,
+                ))
+            } else {
+                Box::new(height::sample_gaussian(
+                    rng,
+                    depth,
+                    dimension.height as f32,
+                    dimension.width as f32,
+                    min_variance,
+                    max_variance,
+                ))
+            }
+        })
+        .collect()
+}
+
+/// Returns every summit of the height map with its prominence, from the highest.
+pub fn peaks(height_map: &HeightMap) -> Vec<Peak> {
+    let rows = height_map.rows();
+    let cols = height_map.cols();
+    let mut order = (0..rows * cols).collect::<Vec<usize>>();
+    order.sort_by_key(|&index| std::cmp::Reverse(height_map.elevations()[index]));
+
+    // Tiles are added from the highest. Each island of added tiles is led by its highest summit,
+    // and when islands meet, the lower summits get their height above the meeting tile as prominence.
+    let mut parent = vec![usize::MAX; rows * cols];
+    let mut summit = vec![0; rows * cols];
+    let mut peaks = Vec::new();
+    for &index in &order {
+        let (i, j) = (index / cols, index % cols);
+        let elevation = height_map.elevation(i, j);
+        let mut islands = Vec::with_capacity(4);
+        for (ni, nj) in neighbours(i, j, rows, cols) {
+            if parent[ni * cols + nj] != usize::MAX {
+                islands.push(find(&mut parent, ni * cols + nj));
+            }
+        }
+        islands.sort_unstable();
+        islands.dedup();
+        let Some(&high) = islands
+            .iter()
+            .max_by_key(|&&island| height_map.elevations()[summit[island]])
+        else {
+            parent[index] = index;
+            summit[index] = index;
+            continue;
+        };
+        for &low in islands.iter().filter(|&&island| island != high) {
+            let top = summit[low];
+            peaks.push(Peak {
+                row: top / cols,
+                col: top % cols,
+                elevation: height_map.elevations()[top],
+                prominence: height_map.elevations()[top] - elevation,
+                saddle: Some((i, j)),
+            });
+            parent[low] = high;
+        }
+        parent[index] = high;
+    }
+
+    if let (Some(&highest), Some(&lowest)) = (order.first(), order.last()) {
+        let elevation = height_map.elevations()[highest];
+        peaks.push(Peak {
+            row: highest / cols,
+            col: highest % cols,
+            elevation,
+            prominence: elevation - height_map.elevations()[lowest],
+            saddle: None,
+        });
+    }
+    peaks.sort_by_key(|peak| std::cmp::Reverse((peak.elevation, peak.prominence)));
+    peaks
+}
+
+fn find(parent: &mut [usize], mut index: usize) -> usize {
+    while parent[index] != index {
+        parent[index] = parent[parent[index]];
+        index = parent[index];
+    }
+    index
+}
+
+/// Returns the summits whose prominence is at least `prominence`, a fraction of the elevation range of the map.
+pub fn mountains(height_map: &HeightMap, prominence: f32) -> Vec<Peak> {
+    let threshold = prominence_threshold(height_map, prominence);
+    peaks(height_map)
+        .into_iter()
+        .filter(|peak| peak.prominence >= threshold)
+        .collect()
+}
+
+fn prominence_threshold(height_map: &HeightMap, prominence: f32) -> usize {
+    let elevations = height_map.elevations();
+    let max = elevations.iter().copied().max().unwrap_or(0);
+    let min = elevations.iter().copied().min().unwrap_or(0);
+    usize::max((prominence * (max - min) as f32).ceil() as usize, 1)
+}
+
+/// Raises gaussians until the map has at least `params.min_mountains` mountains, or gives up after a few tries per missing mountain.
+/// Each new mountain is raised at the tile farthest from the existing ones amongst a few random tiles.
+pub fn ensure_mountains(
+    rng: &mut StdRng,
+    height_map: &mut HeightMap,
+    params: &LandformParams,
+    min_variance: f32,
+    max_variance: f32,
+) {
+    let rows = height_map.rows();
+    let cols = height_map.cols();
+    if rows == 0 || cols == 0 {
+        return;
+    }
+    let mut found = mountains(height_map, params.prominence);
+    let mut attempts = 4 * params.min_mountains;
+    while found.len() < params.min_mountains && attempts > 0 {
+        attempts -= 1;
+        let distance = |(i, j): (usize, usize)| {
+            found
+                .iter()
+                .map(|peak| i.abs_diff(peak.row).pow(2) + j.abs_diff(peak.col).pow(2))
+                .min()
+                .unwrap_or(usize::MAX)
+        };
+        let (row, col) = (0..CANDIDATES)
+            .map(|_| (rng.gen_range(0..rows), rng.gen_range(0..cols)))
+            .max_by_key(|&tile| distance(tile))
+            .unwrap_or((0, 0));
+
+        // Tall enough to stand out even when raised on a slope
+        let elevations = height_map.elevations();
+        let range = elevations.iter().copied().max().unwrap_or(0)
+            - elevations.iter().copied().min().unwrap_or(0);
+        let height = f32::max(range as f32 * params.prominence * 2.0, 2.0);
+        let sigma = Uniform::<f32>::from(min_variance..max_variance).sample(rng) * 0.5;
+        let gaussian = height::Gaussian::new(0.0, sigma, sigma, col as f32, row as f32, height);
+        for i in 0..rows {
+            let Some((start, end)) = gaussian.span_at(i as f32) else {
+                continue;
+            };
+            let start = start.max(0.0).ceil() as usize;
+            let end = usize::min(end.max(0.0).floor() as usize, cols - 1);
+            for j in start..=end {
+                let raise = gaussian.value_at(i as f32, j as f32).round() as usize;
+                height_map.set_elevation(i, j, height_map.elevation(i, j) + raise);
+            }
+        }
+        found = mountains(height_map, params.prominence);
+    }
+}
+
+/// Cuts `params.passes` saddles through the mountain ranges of the height map.
+/// Ranges are the regions above the middle of the elevation range holding at least two summits, the largest first.
+/// Each pass crosses a range at its lowest saddle not crossed yet (see `peaks()`), following a straight corridor across the length of the range
+/// whose floor slopes evenly from the lowland on one side to the lowland on the other. Ranges with a single summit have no saddle and get no pass.
+pub fn cut_passes(height_map: &mut HeightMap, params: &LandformParams) {
+    let elevations = height_map.elevations();
+    let (Some(min), Some(max)) = (
+        elevations.iter().copied().min(),
+        elevations.iter().copied().max(),
+    ) else {
+        return;
+    };
+    let cols = height_map.cols();
+    let level = min as f32 + RANGE_LEVEL * (max - min) as f32;
+    let mut ranges = regions_above(height_map, level);
+    ranges.retain(|range| range.len() >= 9);
+    ranges.sort_by_key(|range| std::cmp::Reverse(range.len()));
+    let mut range_of = vec![usize::MAX; elevations.len()];
+    for (index, range) in ranges.iter().enumerate() {
+        for &(i, j) in range {
+            range_of[i * cols + j] = index;
+        }
+    }
+    // The saddles of each range, from the lowest
+    let mut saddles = vec![Vec::new(); ranges.len()];
+    for (i, j) in peaks(height_map).into_iter().filter_map(|peak| peak.saddle) {
+        if let Some(saddles) = saddles.get_mut(range_of[i * cols + j]) {
+            saddles.push((i, j));
+        }
+    }
+    for saddles in saddles.iter_mut() {
+        saddles.sort_by_key(|&(i, j)| (height_map.elevation(i, j), i, j));
+        saddles.dedup();
+    }
+    let ranges = ranges
+        .into_iter()
+        .zip(saddles)
+        .filter(|(_, saddles)| !saddles.is_empty())
+        .collect::<Vec<(Vec<(usize, usize)>, Vec<(usize, usize)>)>>();
+    if ranges.is_empty() {
+        return;
+    }
+
+    for pass in 0..params.passes {
+        let (range, saddles) = &ranges[pass % ranges.len()];
+        let Some(&(saddle_row, saddle_col)) = saddles.get(pass / ranges.len()) else {
+            continue;
+        };
+        // The range runs along the principal axis of its tiles, the pass runs across it
+        let count = range.len() as f32;
+        let mean_row = range.iter().map(|&(i, _)| i as f32).sum::<f32>() / count;
+        let mean_col = range.iter().map(|&(_, j)| j as f32).sum::<f32>() / count;
+        let (mut var_row, mut var_col, mut covariance) = (0.0, 0.0, 0.0);
+        for &(i, j) in range {
+            let (di, dj) = (i as f32 - mean_row, j as f32 - mean_col);
+            var_row += di * di;
+            var_col += dj * dj;
+            covariance += di * dj;
+        }
+        let angle = 0.5 * f32::atan2(2.0 * covariance, var_row - var_col);
+        let across = (-angle.sin(), angle.cos());
+
+        let center = (saddle_row as f32, saddle_col as f32);
+        let start = walk_out(height_map, center, (-across.0, -across.1), level);
+        let end = walk_out(height_map, center, across, level);
+        carve_corridor(height_map, start, end);
+    }
+}
+
+/// Returns the 4-connected regions of tiles strictly above `level`.
+fn regions_above(height_map: &HeightMap, level: f32) -> Vec<Vec<(usize, usize)>> {
+    let rows = height_map.rows();
+    let cols = height_map.cols();
+    let mut seen = vec![false; rows * cols];
+    let mut regions = Vec::new();
+    for i in 0..rows {
+        for j in 0..cols {
+            if seen[i * cols + j] || height_map.elevation(i, j) as f32 <= level {
+                continue;
+            }
+            seen[i * cols + j] = true;
+            let mut region = Vec::new();
+            let mut queue = VecDeque::from([(i, j)]);
+            while let Some((ci, cj)) = queue.pop_front() {
+                region.push((ci, cj));
+                for (ni, nj) in neighbours(ci, cj, rows, cols) {
+                    if !seen[ni * cols + nj] && height_map.elevation(ni, nj) as f32 > level {
+                        seen[ni * cols + nj] = true;
+                        queue.push_back((ni, nj));
+                    }
+                }
+            }
+            regions.push(region);
+        }
+    }
+    regions
+}
+
+/// Walks from `from` in `direction` until the terrain drops to `level`, then a little further, stopping at the edge of the map.
+fn walk_out(
+    height_map: &HeightMap,
+    from: (f32, f32),
+    direction: (f32, f32),
+    level: f32,
+) -> (usize, usize) {
+    let rows = height_map.rows() as f32;
+    let cols = height_map.cols() as f32;
+    let inside = |(x, y): (f32, f32)| x >= 0.0 && y >= 0.0 && x <= rows - 1.0 && y <= cols - 1.0;
+    let mut position = from;
+    let mut beyond = None;
+    loop {
+        let next = (
+            position.0 + 0.5 * direction.0,
+            position.1 + 0.5 * direction.1,
+        );
+        if !inside(next) {
+            break;
+        }
+        position = next;
+        let tile = (position.0.round() as usize, position.1.round() as usize);
+        match beyond {
+            None if height_map.elevation(tile.0, tile.1) as f32 <= level => beyond = Some(0.0),
+            Some(distance) if distance >= PASS_WIDTH => break,
+            Some(distance) => beyond = Some(distance + 0.5),
+            None => {}
+        }
+    }
+    (position.0.round() as usize, position.1.round() as usize)
+}
+
+/// Lowers the tiles around the segment from `start` to `end` onto a floor sloping evenly between the elevations of both ends.
+/// The floor blends back into the terrain over PASS_WIDTH tiles on each side.
+fn carve_corridor(height_map: &mut HeightMap, start: (usize, usize), end: (usize, usize)) {
+    let rows = height_map.rows();
+    let cols = height_map.cols();
+    let start_elevation = height_map.elevation(start.0, start.1) as f32;
+    let end_elevation = height_map.elevation(end.0, end.1) as f32;
+    let (sx, sy) = (start.0 as f32, start.1 as f32);
+    let (dx, dy) = (end.0 as f32 - sx, end.1 as f32 - sy);
+    let length_squared = dx * dx + dy * dy;
+    let margin = PASS_WIDTH.ceil() as usize;
+    for i in usize::min(start.0, end.0).saturating_sub(margin)
+        ..=usize::min(usize::max(start.0, end.0) + margin, rows - 1)
+    {
+        for j in usize::min(start.1, end.1).saturating_sub(margin)
+            ..=usize::min(usize::max(start.1, end.1) + margin, cols - 1)
+        {
+            let (x, y) = (i as f32, j as f32);
+            let t = if length_squared > 0.0 {
+                (((x - sx) * dx + (y - sy) * dy) / length_squared).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let distance = f32::hypot(sx + t * dx - x, sy + t * dy - y);
+            if distance >= PASS_WIDTH {
+                continue;
+            }
+            let floor = start_elevation + t * (end_elevation - start_elevation);
+            let elevation = height_map.elevation(i, j) as f32;
+            if elevation > floor {
+                let keep = distance / PASS_WIDTH;
+                let keep = keep * keep * (3.0 - 2.0 * keep);
+                height_map.set_elevation(
+                    i,
+                    j,
+                    (floor + (elevation - floor) * keep).round() as usize,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A range running along the middle row, with summits at columns 8 and 22 joined by a saddle at column 15.
+    fn two_summits() -> HeightMap {
+        let crest = |j: usize| -> f32 {
+            let j = j as f32;
+            if j <= 8.0 {
+                20.0 + 10.0 * j
+            } else if j <= 15.0 {
+                100.0 - 30.0 * (j - 8.0) / 7.0
+            } else if j <= 22.0 {
+                70.0 + 20.0 * (j - 15.0) / 7.0
+            } else {
+                90.0 - 70.0 * (j - 22.0) / 8.0
+            }
+        };
+        let elevations = (0..21)
+            .map(|i: usize| {
+                (0..31)
+                    .map(|j| (100.0 + crest(j)).round() as usize - 8 * i.abs_diff(10))
+                    .collect()
+            })
+            .collect::<Vec<Vec<usize>>>();
+        HeightMap::from_elevations(&elevations)
+    }
+
+    #[test]
+    fn peaks_finds_both_summits_and_their_saddle() {
+        let peaks = peaks(&two_summits());
+        assert_eq!(
+            peaks,
+            vec![
+                Peak {
+                    row: 10,
+                    col: 8,
+                    elevation: 200,
+                    prominence: 160,
+                    saddle: None,
+                },
+                Peak {
+                    row: 10,
+                    col: 22,
+                    elevation: 190,
+                    prominence: 20,
+                    saddle: Some((10, 15)),
+                },
+            ]
+        );
+        assert_eq!(mountains(&two_summits(), 0.2).len(), 1);
+    }
+
+    #[test]
+    fn pass_crosses_the_range_below_its_crest() {
+        let mut height_map = two_summits();
+        let params = LandformParams {
+            passes: 1,
+            ..Default::default()
+        };
+        cut_passes(&mut height_map, &params);
+        // The pass goes through the saddle, lower than the crest on both of its sides
+        let pass = (0..21)
+            .map(|i| height_map.elevation(i, 15))
+            .max()
+            .unwrap_or(0);
+        assert!(pass < 170, "the pass rises to {}", pass);
+        assert_eq!(height_map.elevation(10, 8), 200);
+        assert_eq!(height_map.elevation(10, 22), 190);
+        assert!(height_map.elevation(10, 12) > 170 && height_map.elevation(10, 18) > 170);
+    }
+
+    #[test]
+    fn single_summit_gets_no_pass() {
+        let elevations = (0..15)
+            .map(|i: usize| {
+                (0..15)
+                    .map(|j: usize| 100 - 5 * (i.abs_diff(7) + j.abs_diff(7)))
+                    .collect()
+            })
+            .collect::<Vec<Vec<usize>>>();
+        let mut height_map = HeightMap::from_elevations(&elevations);
+        let params = LandformParams {
+            passes: 2,
+            ..Default::default()
+        };
+        cut_passes(&mut height_map, &params);
+        assert_eq!(
+            height_map.elevations(),
+            HeightMap::from_elevations(&elevations).elevations()
+        );
+    }
+}
@@ -1,16 +1,52 @@
+pub mod biome;
+pub mod builder;
+pub mod chunk;
+pub mod climate;
+pub mod connectivity;
+pub mod content;
+pub mod erosion;
 pub mod height;
+pub mod hydrology;
+pub mod landform;
+pub mod normalize;
+pub mod preset;
+pub mod render;
+pub mod save;
+pub mod slope;
+pub mod source;
+pub mod spawn;
 pub mod utils;
+pub mod weather;
 
 use std::collections::HashMap;
 
+use rand::rngs::StdRng;
 use rand::Rng;
+use rand::SeedableRng;
 use robotics_lib::world::environmental_conditions::EnvironmentalConditions;
-use robotics_lib::world::environmental_conditions::WeatherType;
 use robotics_lib::world::tile::Content;
 use robotics_lib::world::tile::Tile;
 use robotics_lib::world::tile::TileType;
 use robotics_lib::world::world_generator::Generator;
-use strum::IntoEnumIterator;
+
+use crate::biome::BiomeThresholds;
+use crate::builder::WorldGeneratorBuilder;
+use crate::climate::{ClimateMap, ClimateParams};
+use crate::connectivity::{ConnectivityMode, ConnectivityParams, ConnectivityReport};
+use crate::content::ContentRule;
+use crate::erosion::ErosionParams;
+use crate::height::kernel::KernelMix;
+use crate::height::ridge::Ridge;
+use crate::height::{BlendMode, GaussianMixture, HeightMap};
+use crate::hydrology::HydrologyParams;
+use crate::landform::LandformParams;
+use crate::normalize::NormalizeParams;
+use crate::preset::Preset;
+use crate::slope::SlopeReport;
+use crate::source::{BlendOp, HeightSource, Stack};
+use crate::spawn::SpawnStrategy;
+use crate::utils::Dimension;
+use crate::weather::{Climate, WeatherConfig};
 
 /// # World Generator
 ///
@@ -18,22 +54,91 @@ use strum::IntoEnumIterator;
 ///
 ///  Elevations are sampled from a set of gaussians in the following way:
 /// The elevation is primarily taken to be the highest value amongst all gaussians. All other gaussians are then added to the elevation with a multiplicative of an interpolation between 0 and 1.
+/// Other ways of merging overlapping gaussians can be picked with `with_blend()` (see `BlendMode`).
+/// Other shapes, such as volcanoes, craters, plateaus, mesas and terraced hills, can be drawn instead of some of the gaussians with `with_kernels()` (see `height::kernel::KernelMix`).
+/// Mountain ranges, gaussians swept along a curve (see `height::ridge::Ridge`), are combined with them in the same way.
+/// They are either sampled at random with `with_ridges()` or given with `with_ridge()`.
 /// ## Parameters
-/// - map_size: The size of the square map
+/// - map_size: The size of the square map. Use `with_dimension()` for a rectangular map.
 /// - amount_mountains: The amount of different gaussians to be spawned.
 /// - scale: Scale of the gaussians.
 /// - interpolation: The impact of gaussians behind the highest on the elevation.
 /// - max_variance: The maximum variance in each direction to draw gaussians from.
 /// - min_variance: The minimum variance in each direction to draw gaussians from.
+///
+/// Further height sources, such as fractal noise, can be blended on top of the gaussians with `with_layer()`.
+/// Alternatively, a height map made beforehand (for instance imported with `height::io`) can be used as is with `with_height_map()`.
+///
+/// Valleys and basins can be carved into the terrain with `with_valleys()`.
+/// Once the height map is made, `with_min_mountains()` guarantees a least amount of mountains standing out from their surroundings (see `with_prominence()`),
+/// and after erosion and normalization, `with_passes()` cuts passes through the mountain ranges at their lowest saddles so that each can be crossed at a low cost.
+///
+/// The height map can then be weathered by thermal and hydraulic erosion, enabled with `with_erosion()`.
+/// Its elevations can finally be rescaled to a target range, remapped by a curve and equalized with `with_normalize()`.
+///
+/// Once elevations are set, tile types are picked from elevation bands (see `BiomeThresholds`), which can be tuned with `with_biomes()`.
+/// With `with_climate_model()`, tile types come from a climate layer instead: temperature falls with elevation and away from the equator,
+/// and moisture is carried by a prevailing wind, so that the lee side of mountains lies in a rain shadow.
+/// Biomes are then looked up from temperature and moisture (see `climate::Biome`), and forests get extra trees.
+/// The temperature, moisture and biome of every tile are available from `climate_map()` after each generation.
+/// Rivers and lakes are then carved from the height map, unless disabled with `with_hydrology(None)`.
+/// Finally content is scattered following a set of `ContentRule`s, which also define the score table returned by the generator.
+///
+/// With `with_max_step()`, slopes are then limited so that no two neighbouring tiles differ in elevation by more than a given amount,
+/// which bounds the cost of every move. How much terrain was changed is available from `slope_report()` after each generation.
+///
+/// The spawn point is chosen last following a `SpawnStrategy`, set with `with_spawn()`, and is always walkable.
+///
+/// With `with_connectivity()`, worlds where too few walkable tiles are reachable from the spawn point are either joined by carved corridors or regenerated.
+/// What was found and done is available from `connectivity_report()` after each generation.
+///
+/// The environmental conditions are set with `with_weather()`, or from a preset with `with_climate()`.
+///
+/// Rather than tuning every parameter, a named terrain can be picked with `with_preset()` (see `Preset`).
+///
+/// `new()` and the `with_*()` methods accept any value, and invalid ones make generation panic, except for the weather which falls back to the default one.
+/// Use `builder()` to get a `GeneratorConfigError` instead.
+///
+/// Every generation stage draws from a single RNG stream seeded with `seed`, so the same seed always produces the same world.
+/// Unless set with `with_seed()`, the seed is drawn at random on construction and can be read back with `seed()`.
+///
+/// For worlds too large to build at once, see `chunk::ChunkGenerator`, which generates them chunk by chunk.
 pub struct WorldGenerator {
-    map_size: usize,
+    dimension: Dimension,
     amount_mountains: usize,
+    amount_ridges: usize,
+    ridges: Vec<Ridge>,
+    kernels: KernelMix,
+    landforms: LandformParams,
     scale: f32,
     interpolation: f32,
     max_variance: f32,
     min_variance: f32,
+    blend: BlendMode,
+    seed: u64,
+    biomes: BiomeThresholds,
+    climate_model: Option<ClimateParams>,
+    climate_map: Option<ClimateMap>,
+    hydrology: Option<HydrologyParams>,
+    content: Vec<ContentRule>,
+    layers: Vec<(BlendOp, Box<dyn HeightSource>)>,
+    erosion: Option<ErosionParams>,
+    normalize: Option<NormalizeParams>,
+    max_step: Option<usize>,
+    slope_report: Option<SlopeReport>,
+    height_map: Option<HeightMap>,
+    weather: WeatherConfig,
+    conditions: EnvironmentalConditions,
+    spawn: SpawnStrategy,
+    connectivity: Option<ConnectivityParams>,
+    connectivity_report: Option<ConnectivityReport>,
 }
 impl WorldGenerator {
+    /// Returns a builder checking the configuration before creating the generator.
+    pub fn builder() -> WorldGeneratorBuilder {
+        WorldGeneratorBuilder::default()
+    }
+
     pub fn new(
         map_size: usize,
         amount_mountains: usize,
@@ -43,18 +148,351 @@ impl WorldGenerator {
         min_variance: f32,
     ) -> WorldGenerator {
         WorldGenerator {
-            map_size,
+            dimension: Dimension {
+                width: map_size,
+                height: map_size,
+            },
             amount_mountains,
+            amount_ridges: 0,
+            ridges: Vec::new(),
+            kernels: KernelMix::default(),
+            landforms: LandformParams::default(),
             scale,
             interpolation,
             max_variance,
             min_variance,
+            blend: BlendMode::default(),
+            seed: rand::thread_rng().gen(),
+            biomes: BiomeThresholds::default(),
+            climate_model: None,
+            climate_map: None,
+            hydrology: Some(HydrologyParams::default()),
+            content: content::default_rules(),
+            layers: Vec::new(),
+            erosion: None,
+            normalize: None,
+            max_step: None,
+            slope_report: None,
+            height_map: None,
+            weather: WeatherConfig::default(),
+            conditions: weather::default_conditions(),
+            spawn: SpawnStrategy::default(),
+            connectivity: None,
+            connectivity_report: None,
         }
     }
+
+    /// Sets the seed used by every generation stage.
+    pub fn with_seed(mut self, seed: u64) -> WorldGenerator {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the dimension of the map, producing a world with `dimension.height` rows and `dimension.width` columns.
+    pub fn with_dimension(mut self, dimension: Dimension) -> WorldGenerator {
+        self.dimension = dimension;
+        self
+    }
+
+    /// Sets the gaussians, kernels, mountain ranges, blend mode, erosion, normalization, biomes and weather from a named terrain, tuned for the current dimension.
+    /// Set the dimension first, and any parameter set afterwards overrides the one of the preset.
+    pub fn with_preset(mut self, preset: Preset) -> WorldGenerator {
+        let params = preset.params(&self.dimension);
+        self.amount_mountains = params.amount_mountains;
+        self.amount_ridges = params.amount_ridges;
+        self.kernels = params.kernels;
+        self.scale = params.scale;
+        self.interpolation = params.interpolation;
+        self.max_variance = params.max_variance;
+        self.min_variance = params.min_variance;
+        self.with_blend(params.blend)
+            .with_erosion(params.erosion)
+            .with_normalize(params.normalize)
+            .with_biomes(params.biomes)
+            .with_climate(params.climate)
+    }
+
+    /// Sets the relative amounts of each kind of kernel drawn in place of the gaussians.
+    pub fn with_kernels(mut self, kernels: KernelMix) -> WorldGenerator {
+        self.kernels = kernels;
+        self
+    }
+
+    /// Sets the amount of valleys and basins carved into the terrain.
+    pub fn with_valleys(mut self, valleys: usize) -> WorldGenerator {
+        self.landforms.valleys = valleys;
+        self
+    }
+
+    /// Sets the least amount of mountains of the map. Missing ones are raised where the map is the flattest.
+    pub fn with_min_mountains(mut self, min_mountains: usize) -> WorldGenerator {
+        self.landforms.min_mountains = min_mountains;
+        self
+    }
+
+    /// Sets how far above its surroundings a peak must rise to count as a mountain, as a fraction between 0 and 1 of the elevation range.
+    pub fn with_prominence(mut self, prominence: f32) -> WorldGenerator {
+        self.landforms.prominence = prominence;
+        self
+    }
+
+    /// Sets the amount of passes cut through the mountain ranges.
+    pub fn with_passes(mut self, passes: usize) -> WorldGenerator {
+        self.landforms.passes = passes;
+        self
+    }
+
+    /// Sets the amount of mountain ranges sampled at random alongside the gaussians.
+    pub fn with_ridges(mut self, amount_ridges: usize) -> WorldGenerator {
+        self.amount_ridges = amount_ridges;
+        self
+    }
+
+    /// Adds a mountain range along a given spine, on top of the sampled ones.
+    pub fn with_ridge(mut self, ridge: Ridge) -> WorldGenerator {
+        self.ridges.push(ridge);
+        self
+    }
+
+    /// Sets how overlapping gaussians are combined.
+    pub fn with_blend(mut self, blend: BlendMode) -> WorldGenerator {
+        self.blend = blend;
+        self
+    }
+
+    /// Adds a height source blended on top of the gaussians and of the previously added layers.
+    pub fn with_layer(mut self, op: BlendOp, source: Box<dyn HeightSource>) -> WorldGenerator {
+        self.layers.push((op, source));
+        self
+    }
+
+    /// Uses the given height map instead of sampling gaussians. The dimension of the world becomes the one of the map.
+    pub fn with_height_map(mut self, height_map: HeightMap) -> WorldGenerator {
+        self.dimension = Dimension {
+            width: height_map.cols(),
+            height: height_map.rows(),
+        };
+        self.height_map = Some(height_map);
+        self
+    }
+
+    /// Sets the erosion passes run on the height map. `None` disables erosion.
+    pub fn with_erosion(mut self, erosion: Option<ErosionParams>) -> WorldGenerator {
+        self.erosion = erosion;
+        self
+    }
+
+    /// Sets how elevations are rescaled once the height map is done. `None` keeps them as they are.
+    pub fn with_normalize(mut self, normalize: Option<NormalizeParams>) -> WorldGenerator {
+        self.normalize = normalize;
+        self
+    }
+
+    /// Sets the elevation bands used to pick tile types.
+    pub fn with_biomes(mut self, biomes: BiomeThresholds) -> WorldGenerator {
+        self.biomes = biomes;
+        self
+    }
+
+    /// Sets the parameters of the climate model picking tile types. `None` picks them from the elevation bands alone.
+    pub fn with_climate_model(mut self, climate_model: Option<ClimateParams>) -> WorldGenerator {
+        self.climate_model = climate_model;
+        self
+    }
+
+    /// Returns the climate of the last generated world, if the climate model is enabled.
+    pub fn climate_map(&self) -> Option<&ClimateMap> {
+        self.climate_map.as_ref()
+    }
+
+    /// Sets the parameters of the hydrology stage. `None` disables rivers and lakes.
+    pub fn with_hydrology(mut self, hydrology: Option<HydrologyParams>) -> WorldGenerator {
+        self.hydrology = hydrology;
+        self
+    }
+
+    /// Sets the rules used to place content. An empty set leaves every tile empty.
+    pub fn with_content(mut self, content: Vec<ContentRule>) -> WorldGenerator {
+        self.content = content;
+        self
+    }
+
+    /// Sets the weather forecast cycle, the time progression and the starting hour.
+    /// A configuration refused by robotics_lib is replaced by the default weather, which `weather()` then returns.
+    pub fn with_weather(mut self, weather: WeatherConfig) -> WorldGenerator {
+        match weather.conditions() {
+            Ok(conditions) => {
+                self.conditions = conditions;
+                self.weather = weather;
+            }
+            Err(_) => {
+                self.conditions = weather::default_conditions();
+                self.weather = WeatherConfig::default();
+            }
+        }
+        self
+    }
+
+    /// Sets the weather from a climate preset.
+    pub fn with_climate(self, climate: Climate) -> WorldGenerator {
+        self.with_weather(climate.weather())
+    }
+
+    /// Sets how the spawn point is chosen.
+    pub fn with_spawn(mut self, spawn: SpawnStrategy) -> WorldGenerator {
+        self.spawn = spawn;
+        self
+    }
+
+    /// Sets the highest elevation difference allowed between neighbouring tiles. `None` leaves slopes untouched.
+    pub fn with_max_step(mut self, max_step: Option<usize>) -> WorldGenerator {
+        self.max_step = max_step;
+        self
+    }
+
+    /// Returns the report of the slope limiting stage for the last generated world, if the stage is enabled.
+    pub fn slope_report(&self) -> Option<&SlopeReport> {
+        self.slope_report.as_ref()
+    }
+
+    /// Sets the connectivity guarantee. `None` disables it.
+    pub fn with_connectivity(mut self, connectivity: Option<ConnectivityParams>) -> WorldGenerator {
+        self.connectivity = connectivity;
+        self
+    }
+
+    /// Returns the report of the connectivity stage for the last generated world, if the stage is enabled.
+    pub fn connectivity_report(&self) -> Option<&ConnectivityReport> {
+        self.connectivity_report.as_ref()
+    }
+
+    /// Returns the weather configuration the environmental conditions are built from.
+    pub fn weather(&self) -> &WeatherConfig {
+        &self.weather
+    }
+
+    /// Returns the seed the world is generated from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
 }
 
 type World = Vec<Vec<Tile>>;
 
+impl WorldGenerator {
+    /// Runs every generation stage once, drawing from the given rng.
+    /// Returns the world, the spawn point, the max score and the score table.
+    fn build(&mut self, rng: &mut StdRng) -> (World, (usize, usize), f32, HashMap<Content, f32>) {
+        // Reports of a previous world must not outlive it when a stage is disabled
+        self.slope_report = None;
+        self.climate_map = None;
+
+        let mut world = Vec::new();
+        for _ in 0..self.dimension.height {
+            let mut row = Vec::new();
+            for _ in 0..self.dimension.width {
+                row.push(Tile {
+                    tile_type: TileType::Grass,
+                    content: Content::None,
+                    elevation: 0,
+                });
+            }
+            world.push(row);
+        }
+
+        let mut height_map = match &self.height_map {
+            Some(height_map) => height_map.clone(),
+            None => {
+                let mixture = GaussianMixture::sample_mix(
+                    rng,
+                    &self.dimension,
+                    self.amount_mountains,
+                    &self.kernels,
+                    self.scale,
+                    self.interpolation,
+                    self.min_variance,
+                    self.max_variance,
+                )
+                .with_blend(self.blend);
+                let ridges = (0..self.amount_ridges)
+                    .map(|_| {
+                        Ridge::sample(
+                            rng,
+                            &self.dimension,
+                            self.scale,
+                            self.min_variance,
+                            self.max_variance,
+                        )
+                    })
+                    .chain(self.ridges.iter().cloned())
+                    .collect();
+                let valleys = landform::sample_valleys(
+                    rng,
+                    &self.dimension,
+                    self.landforms.valleys,
+                    self.scale,
+                    self.min_variance,
+                    self.max_variance,
+                );
+                let mixture = mixture.with_ridges(ridges).with_carvers(valleys);
+                let source = Stack {
+                    base: &mixture,
+                    layers: &self.layers,
+                };
+                height::height_map_from_source(&source, &self.dimension)
+            }
+        };
+        if self.landforms.min_mountains > 0 {
+            landform::ensure_mountains(
+                rng,
+                &mut height_map,
+                &self.landforms,
+                self.min_variance,
+                self.max_variance,
+            );
+        }
+        if let Some(erosion) = &self.erosion {
+            erosion::erode(rng, &mut height_map, erosion);
+        }
+        if let Some(params) = &self.normalize {
+            normalize::normalize(&mut height_map, params);
+        }
+        if self.landforms.passes > 0 {
+            landform::cut_passes(&mut height_map, &self.landforms);
+        }
+        if let Some(max_step) = self.max_step {
+            self.slope_report = Some(slope::limit(&mut height_map, max_step));
+        }
+        let water = self
+            .hydrology
+            .as_ref()
+            .map(|params| hydrology::compute(&height_map, params));
+        height::bump_world(&mut world, height_map);
+        let climate_map = self
+            .climate_model
+            .as_ref()
+            .map(|params| climate::compute(&world, &self.biomes, params));
+        match &climate_map {
+            Some(climate_map) => climate::classify(&mut world, &self.biomes, climate_map),
+            None => biome::classify(&mut world, &self.biomes),
+        }
+        if let (Some(water), Some(params)) = (&water, &self.hydrology) {
+            hydrology::apply(&mut world, water, params);
+        }
+        let mut forest_score = 0.0;
+        if let (Some(climate_map), Some(params)) = (&climate_map, &self.climate_model) {
+            forest_score =
+                climate::plant_forests(rng, &mut world, climate_map, params, &self.content);
+        }
+        self.climate_map = climate_map;
+        let (max_score, score_table) = content::place(rng, &mut world, &self.content);
+        let max_score = max_score + forest_score;
+        let spawn = spawn::choose(rng, &mut world, &self.spawn);
+
+        (world, spawn, max_score, score_table)
+    }
+}
+
 impl Generator for WorldGenerator {
     fn gen(
         &mut self,
@@ -65,39 +503,63 @@ impl Generator for WorldGenerator {
         f32,
         Option<HashMap<Content, f32>>,
     ) {
-        let mut rng = rand::thread_rng();
-        let mut world = Vec::new();
-        for _ in 0..self.map_size {
-            let mut row = Vec::new();
-            for _ in 0..self.map_size {
-                row.push(Tile {
-                    tile_type: match rng.gen_range(0..TileType::iter().len()) {
-                        1 => TileType::Sand,
-                        _ => TileType::Grass,
-                    },
-                    content: Content::None,
-                    elevation: 0,
-                });
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let (mut world, mut spawn, mut max_score, mut score_table) = self.build(&mut rng);
+        self.connectivity_report = None;
+        if let Some(params) = self.connectivity.clone() {
+            let mut attempts = 1;
+            let (mut fraction, mut disconnected) = connectivity::analyze(&world, spawn);
+            if let ConnectivityMode::Regenerate {
+                attempts: max_attempts,
+            } = params.mode
+            {
+                while fraction < params.min_reachable && attempts < max_attempts {
+                    (world, spawn, max_score, score_table) = self.build(&mut rng);
+                    (fraction, disconnected) = connectivity::analyze(&world, spawn);
+                    attempts += 1;
+                }
             }
-            world.push(row);
+            let carved = connectivity::carve(&mut world, spawn, &params);
+            self.connectivity_report = Some(ConnectivityReport {
+                attempts,
+                disconnected,
+                carved,
+                reachable_fraction: connectivity::analyze(&world, spawn).0,
+            });
         }
 
-        let height_map = height::create_height_map(
-            self.map_size,
-            self.amount_mountains,
-            self.scale,
-            self.interpolation,
-            self.min_variance,
-            self.max_variance,
-        );
-        height::bump_world(&mut world, height_map);
-
         (
             world,
-            (0, 0),
-            EnvironmentalConditions::new(&[WeatherType::Sunny], 1, 1).unwrap(),
-            10.0,
-            None,
+            spawn,
+            self.conditions.clone(),
+            max_score,
+            Some(score_table),
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slope_report_is_cleared_when_the_stage_is_disabled() {
+        let mut generator = WorldGenerator::new(32, 6, 30.0, 0.1, 8.0, 3.0).with_max_step(Some(2));
+        generator.gen();
+        assert!(generator.slope_report().is_some());
+        let mut generator = generator.with_max_step(None);
+        generator.gen();
+        assert!(generator.slope_report().is_none());
+    }
+
+    #[test]
+    fn connectivity_report_is_cleared_when_the_stage_is_disabled() {
+        let mut generator = WorldGenerator::new(32, 6, 30.0, 0.1, 8.0, 3.0)
+            .with_connectivity(Some(ConnectivityParams::default()));
+        generator.gen();
+        assert!(generator.connectivity_report().is_some());
+        let mut generator = generator.with_connectivity(None);
+        generator.gen();
+        assert!(generator.connectivity_report().is_none());
+    }
+}
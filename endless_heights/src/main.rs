@@ -0,0 +1,604 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Parser, ValueEnum};
+use endless_heights::biome::BiomeThresholds;
+use endless_heights::climate::{ClimateParams, Wind};
+use endless_heights::connectivity::{ConnectivityMode, ConnectivityParams};
+use endless_heights::content;
+use endless_heights::erosion::{ErosionParams, HydraulicErosionParams, ThermalErosionParams};
+use endless_heights::height::io::{self, BitDepth, Format};
+use endless_heights::height::kernel::{KernelKind, KernelMix};
+use endless_heights::height::{BlendMode, HeightMap};
+use endless_heights::hydrology::HydrologyParams;
+use endless_heights::normalize::{Curve, NormalizeParams};
+use endless_heights::preset::Preset;
+use endless_heights::render::{self, RenderOptions};
+use endless_heights::save::{SaveFormat, SavedWorld};
+use endless_heights::spawn::SpawnStrategy;
+use endless_heights::utils::Dimension;
+use endless_heights::weather::Climate;
+use endless_heights::WorldGenerator;
+use robotics_lib::world::tile::Content;
+
+/// Generates worlds with the Endless Heights generator and exports them.
+///
+/// Custom height sources, hand-placed mountain ranges, imported height maps, the tile types, quantities and scores of content rules,
+/// and the fine tuning of erosion, normalization curves and the climate model are only available from the library.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about)]
+struct Args {
+    /// Size of the square map
+    #[arg(long, default_value_t = 100)]
+    size: usize,
+    /// Width of the map, overrides size
+    #[arg(long)]
+    width: Option<usize>,
+    /// Height of the map, overrides size
+    #[arg(long)]
+    height: Option<usize>,
+    /// Named terrain. The options below override its parameters
+    #[arg(long, value_enum)]
+    preset: Option<PresetArg>,
+    /// Amount of gaussians to be spawned [default: 20]
+    #[arg(long)]
+    mountains: Option<usize>,
+    /// Relative amounts of each kind of kernel, as kind=weight pairs separated by commas [default: gaussian=1]
+    /// Kinds are gaussian, volcano, crater, plateau, mesa and terraced-hill
+    #[arg(long, value_parser = parse_kernel_mix)]
+    kernels: Option<KernelMix>,
+    /// Amount of mountain ranges to be spawned [default: 0]
+    #[arg(long)]
+    ridges: Option<usize>,
+    /// Amount of valleys and basins to be carved
+    #[arg(long, default_value_t = 0)]
+    valleys: usize,
+    /// Least amount of mountains standing out from their surroundings
+    #[arg(long, default_value_t = 0)]
+    min_mountains: usize,
+    /// Fraction of the elevation range a peak must rise above its surroundings to count as a mountain
+    #[arg(long, default_value_t = 0.2)]
+    prominence: f32,
+    /// Amount of passes to be cut through the mountain ranges
+    #[arg(long, default_value_t = 0)]
+    passes: usize,
+    /// Scale of the gaussians [default: 30]
+    #[arg(long)]
+    scale: Option<f32>,
+    /// Impact of gaussians behind the highest on the elevation [default: 0.1]
+    #[arg(long)]
+    interpolation: Option<f32>,
+    /// How overlapping gaussians are combined [default: weighted]
+    #[arg(long, value_enum)]
+    blend: Option<BlendArg>,
+    /// Sharpness of the smooth-max blend
+    #[arg(long, default_value_t = 1.0)]
+    sharpness: f32,
+    /// Maximum variance in each direction to draw gaussians from [default: 20]
+    #[arg(long)]
+    max_variance: Option<f32>,
+    /// Minimum variance in each direction to draw gaussians from [default: 5]
+    #[arg(long)]
+    min_variance: Option<f32>,
+    /// Seed of the world, random if not set
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Weather preset
+    #[arg(long, value_enum)]
+    climate: Option<ClimateArg>,
+    /// Picks tile types from temperature and moisture instead of elevation alone
+    #[arg(long)]
+    climate_model: bool,
+    /// Direction the prevailing wind of the climate model blows from. Implies --climate-model [default: west]
+    #[arg(long, value_enum)]
+    wind: Option<WindArg>,
+    /// How the spawn point is chosen
+    #[arg(long, value_enum, default_value_t = SpawnArg::LargestRegion)]
+    spawn: SpawnArg,
+    /// Spawn tile as row,col. Implies the fixed spawn strategy
+    #[arg(long, value_parser = parse_position)]
+    spawn_at: Option<(usize, usize)>,
+    /// Spawns next to the given content, as named in --content-density. Implies the near-content spawn strategy
+    #[arg(long, value_parser = parse_content)]
+    spawn_near: Option<Content>,
+    /// Upper bounds of the elevation bands, as band=fraction pairs separated by commas [default: the ones of the preset]
+    /// Bands are deep-water, shallow-water, sand, grass, hill and mountain
+    #[arg(long, value_parser = parse_biomes)]
+    biomes: Option<Bands>,
+    /// Probability for each eligible tile to get a content of the default rules, as content=density pairs separated by commas
+    /// Contents are tree, rock, fish, coin, garbage, bank and market
+    #[arg(long, value_parser = parse_densities)]
+    content_density: Option<Densities>,
+    /// Disables content
+    #[arg(long)]
+    no_content: bool,
+    /// Disables rivers and lakes
+    #[arg(long)]
+    no_hydrology: bool,
+    /// Amount of upstream tiles that must drain through a tile for it to become a river [default: 40]
+    #[arg(long)]
+    river_threshold: Option<usize>,
+    /// Minimum amount of tiles a basin must cover to become a lake [default: 4]
+    #[arg(long)]
+    min_lake_size: Option<usize>,
+    /// Depth below the water surface from which lakes are deep water [default: 3]
+    #[arg(long)]
+    deep_lake_depth: Option<usize>,
+    /// Runs thermal and hydraulic erosion
+    #[arg(long)]
+    erosion: bool,
+    /// Rescales elevations so that the lowest tile is at this elevation
+    #[arg(long)]
+    min_elevation: Option<usize>,
+    /// Rescales elevations so that the highest tile is at this elevation
+    #[arg(long)]
+    max_elevation: Option<usize>,
+    /// Raises rescaled elevations to this power
+    #[arg(long)]
+    power: Option<f32>,
+    /// Equalizes the elevation histogram
+    #[arg(long)]
+    equalize: bool,
+    /// Highest elevation difference allowed between neighbouring tiles
+    #[arg(long)]
+    max_step: Option<usize>,
+    /// Fraction of walkable tiles that must be reachable from the spawn point
+    #[arg(long)]
+    min_reachable: Option<f32>,
+    /// Regenerates up to this amount of times instead of carving corridors when the world is not connected enough
+    #[arg(long)]
+    regenerate: Option<usize>,
+    /// Output format
+    #[arg(long, short, value_enum, default_value_t = OutputFormat::Ansi)]
+    format: OutputFormat,
+    /// Output path. ANSI previews and JSON worlds go to the standard output when not set
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+    /// Does not print statistics
+    #[arg(long, short)]
+    quiet: bool,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum PresetArg {
+    Plains,
+    RollingHills,
+    Alpine,
+    Archipelago,
+    Canyonlands,
+    SingleVolcano,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum BlendArg {
+    Max,
+    Sum,
+    Weighted,
+    SmoothMax,
+    Multiplicative,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ClimateArg {
+    Temperate,
+    Alpine,
+    Tropical,
+    Arid,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum WindArg {
+    North,
+    East,
+    South,
+    West,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum SpawnArg {
+    LowestValley,
+    Random,
+    LargestRegion,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Saved world as JSON
+    WorldJson,
+    /// Saved world in the binary format
+    WorldBin,
+    /// 8 bit grayscale PGM height map
+    Pgm8,
+    /// 16 bit grayscale PGM height map
+    Pgm16,
+    /// 8 bit grayscale PNG height map
+    Png8,
+    /// 16 bit grayscale PNG height map
+    Png16,
+    /// Raw little-endian u16 height map
+    Raw16,
+    /// Colored preview for truecolor terminals
+    Ansi,
+}
+
+fn parse_position(value: &str) -> Result<(usize, usize), String> {
+    let (row, col) = value
+        .split_once(',')
+        .ok_or_else(|| format!("expected row,col, found {}", value))?;
+    let parse = |part: &str| {
+        part.trim()
+            .parse::<usize>()
+            .map_err(|error| format!("{}: {}", part, error))
+    };
+    Ok((parse(row)?, parse(col)?))
+}
+
+fn parse_content(value: &str) -> Result<Content, String> {
+    match value.trim() {
+        "tree" => Ok(Content::Tree(0)),
+        "rock" => Ok(Content::Rock(0)),
+        "fish" => Ok(Content::Fish(0)),
+        "coin" => Ok(Content::Coin(0)),
+        "garbage" => Ok(Content::Garbage(0)),
+        "bank" => Ok(Content::Bank(0..0)),
+        "market" => Ok(Content::Market(0)),
+        other => Err(format!("unknown content {}", other)),
+    }
+}
+
+/// Parses name=value pairs separated by commas.
+fn parse_pairs<T>(
+    value: &str,
+    parse_name: impl Fn(&str) -> Result<T, String>,
+) -> Result<Vec<(T, f32)>, String> {
+    value
+        .split(',')
+        .map(|pair| {
+            let (name, number) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected name=value, found {}", pair))?;
+            let number = number
+                .trim()
+                .parse::<f32>()
+                .map_err(|error| format!("{}: {}", number, error))?;
+            Ok((parse_name(name.trim())?, number))
+        })
+        .collect()
+}
+
+/// Upper bounds of elevation bands, by name.
+#[derive(Clone, Debug)]
+struct Bands(Vec<(String, f32)>);
+
+/// Densities of content rules, by content.
+#[derive(Clone, Debug)]
+struct Densities(Vec<(Content, f32)>);
+
+fn parse_biomes(value: &str) -> Result<Bands, String> {
+    parse_pairs(value, |name| match name {
+        "deep-water" | "shallow-water" | "sand" | "grass" | "hill" | "mountain" => {
+            Ok(name.to_string())
+        }
+        other => Err(format!("unknown band {}", other)),
+    })
+    .map(Bands)
+}
+
+fn parse_densities(value: &str) -> Result<Densities, String> {
+    parse_pairs(value, parse_content).map(Densities)
+}
+
+fn parse_kernel_mix(value: &str) -> Result<KernelMix, String> {
+    let mut mix = KernelMix {
+        gaussian: 0.0,
+        ..KernelMix::default()
+    };
+    for pair in value.split(',') {
+        let (kind, weight) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("expected kind=weight, found {}", pair))?;
+        let kind = match kind.trim() {
+            "gaussian" => KernelKind::Gaussian,
+            "volcano" => KernelKind::Volcano,
+            "crater" => KernelKind::Crater,
+            "plateau" => KernelKind::Plateau,
+            "mesa" => KernelKind::Mesa,
+            "terraced-hill" => KernelKind::TerracedHill,
+            other => return Err(format!("unknown kernel {}", other)),
+        };
+        *mix.weight_mut(kind) = weight
+            .trim()
+            .parse::<f32>()
+            .map_err(|error| format!("{}: {}", weight, error))?;
+    }
+    Ok(mix)
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let dimension = Dimension {
+        width: args.width.unwrap_or(args.size),
+        height: args.height.unwrap_or(args.size),
+    };
+    let preset = args.preset.map(|preset| match preset {
+        PresetArg::Plains => Preset::Plains,
+        PresetArg::RollingHills => Preset::RollingHills,
+        PresetArg::Alpine => Preset::Alpine,
+        PresetArg::Archipelago => Preset::Archipelago,
+        PresetArg::Canyonlands => Preset::Canyonlands,
+        PresetArg::SingleVolcano => Preset::SingleVolcano,
+    });
+    let mut builder = WorldGenerator::builder()
+        .dimension(dimension.clone())
+        .spawn(match (args.spawn_at, &args.spawn_near, args.spawn) {
+            (Some(position), _, _) => SpawnStrategy::Fixed(position),
+            (None, Some(content), _) => SpawnStrategy::NearContent(content.clone()),
+            (None, None, SpawnArg::LowestValley) => SpawnStrategy::LowestValley,
+            (None, None, SpawnArg::Random) => SpawnStrategy::RandomWalkable,
+            (None, None, SpawnArg::LargestRegion) => SpawnStrategy::LargestRegion,
+        });
+    if let Some(preset) = preset {
+        builder = builder.preset(preset);
+    }
+    if let Some(mountains) = args.mountains {
+        builder = builder.amount_mountains(mountains);
+    }
+    if let Some(kernels) = &args.kernels {
+        builder = builder.kernels(kernels.clone());
+    }
+    if let Some(ridges) = args.ridges {
+        builder = builder.amount_ridges(ridges);
+    }
+    builder = builder
+        .valleys(args.valleys)
+        .min_mountains(args.min_mountains)
+        .prominence(args.prominence)
+        .passes(args.passes);
+    if let Some(scale) = args.scale {
+        builder = builder.scale(scale);
+    }
+    if let Some(interpolation) = args.interpolation {
+        builder = builder.interpolation(interpolation);
+    }
+    if let Some(blend) = args.blend {
+        builder = builder.blend(match blend {
+            BlendArg::Max => BlendMode::Max,
+            BlendArg::Sum => BlendMode::Sum,
+            BlendArg::Weighted => BlendMode::Weighted,
+            BlendArg::SmoothMax => BlendMode::SmoothMax {
+                sharpness: args.sharpness,
+            },
+            BlendArg::Multiplicative => BlendMode::Multiplicative,
+        });
+    }
+    if let Some(min_variance) = args.min_variance {
+        builder = builder.min_variance(min_variance);
+    }
+    if let Some(max_variance) = args.max_variance {
+        builder = builder.max_variance(max_variance);
+    }
+    if let Some(Bands(bands)) = &args.biomes {
+        let mut biomes = preset.map_or_else(BiomeThresholds::default, |preset| {
+            preset.params(&dimension).biomes
+        });
+        for (band, threshold) in bands {
+            *match band.as_str() {
+                "deep-water" => &mut biomes.deep_water,
+                "shallow-water" => &mut biomes.shallow_water,
+                "sand" => &mut biomes.sand,
+                "grass" => &mut biomes.grass,
+                "hill" => &mut biomes.hill,
+                _ => &mut biomes.mountain,
+            } = *threshold;
+        }
+        builder = builder.biomes(biomes);
+    }
+    if args.no_content {
+        builder = builder.content(Vec::new());
+    } else if let Some(Densities(densities)) = &args.content_density {
+        let mut rules = content::default_rules();
+        for (content, density) in densities {
+            for rule in rules.iter_mut() {
+                if std::mem::discriminant(&rule.content) == std::mem::discriminant(content) {
+                    rule.density = *density;
+                }
+            }
+        }
+        builder = builder.content(rules);
+    }
+    if let Some(seed) = args.seed {
+        builder = builder.seed(seed);
+    }
+    if let Some(climate) = args.climate {
+        builder = builder.climate(match climate {
+            ClimateArg::Temperate => Climate::Temperate,
+            ClimateArg::Alpine => Climate::Alpine,
+            ClimateArg::Tropical => Climate::Tropical,
+            ClimateArg::Arid => Climate::Arid,
+        });
+    }
+    if args.climate_model || args.wind.is_some() {
+        builder = builder.climate_model(Some(ClimateParams {
+            wind: match args.wind {
+                Some(WindArg::North) => Wind::North,
+                Some(WindArg::East) => Wind::East,
+                Some(WindArg::South) => Wind::South,
+                Some(WindArg::West) | None => Wind::West,
+            },
+            ..Default::default()
+        }));
+    }
+    if args.no_hydrology {
+        builder = builder.hydrology(None);
+    } else {
+        let defaults = HydrologyParams::default();
+        builder = builder.hydrology(Some(HydrologyParams {
+            river_threshold: args.river_threshold.unwrap_or(defaults.river_threshold),
+            min_lake_size: args.min_lake_size.unwrap_or(defaults.min_lake_size),
+            deep_lake_depth: args.deep_lake_depth.unwrap_or(defaults.deep_lake_depth),
+        }));
+    }
+    if args.erosion {
+        builder = builder.erosion(Some(ErosionParams {
+            thermal: Some(ThermalErosionParams::default()),
+            hydraulic: Some(HydraulicErosionParams::default()),
+        }));
+    }
+    if args.min_elevation.is_some()
+        || args.max_elevation.is_some()
+        || args.power.is_some()
+        || args.equalize
+    {
+        let defaults = NormalizeParams::default();
+        builder = builder.normalize(Some(NormalizeParams {
+            min_elevation: args.min_elevation.unwrap_or(defaults.min_elevation),
+            max_elevation: args.max_elevation.unwrap_or(defaults.max_elevation),
+            curve: args.power.map_or(Curve::Linear, Curve::Power),
+            equalize: args.equalize,
+        }));
+    }
+    builder = builder.max_step(args.max_step);
+    if args.min_reachable.is_some() || args.regenerate.is_some() {
+        builder = builder.connectivity(Some(ConnectivityParams {
+            min_reachable: args.min_reachable.unwrap_or(1.0),
+            mode: match args.regenerate {
+                Some(attempts) => ConnectivityMode::Regenerate { attempts },
+                None => ConnectivityMode::Carve,
+            },
+            ..Default::default()
+        }));
+    }
+    let mut generator = builder.build().map_err(|e| e.to_string())?;
+
+    let saved = SavedWorld::generate(&mut generator);
+    let world = saved.world();
+    let height_map = HeightMap::from_elevations(
+        &world
+            .iter()
+            .map(|row| row.iter().map(|tile| tile.elevation).collect())
+            .collect::<Vec<Vec<usize>>>(),
+    );
+
+    let bytes = match args.format {
+        OutputFormat::WorldJson => saved.encode(SaveFormat::Json).map_err(|e| e.to_string())?,
+        OutputFormat::WorldBin => saved
+            .encode(SaveFormat::Binary)
+            .map_err(|e| e.to_string())?,
+        OutputFormat::Pgm8 => encode_height_map(&height_map, Format::Pgm(BitDepth::Eight))?,
+        OutputFormat::Pgm16 => encode_height_map(&height_map, Format::Pgm(BitDepth::Sixteen))?,
+        OutputFormat::Png8 => encode_height_map(&height_map, Format::Png(BitDepth::Eight))?,
+        OutputFormat::Png16 => encode_height_map(&height_map, Format::Png(BitDepth::Sixteen))?,
+        OutputFormat::Raw16 => encode_height_map(&height_map, Format::RawU16)?,
+        OutputFormat::Ansi => render::render_world(
+            &world,
+            &RenderOptions {
+                marker: Some(saved.spawn),
+                ..Default::default()
+            },
+        )
+        .into_bytes(),
+    };
+    let to_stdout = args.output.is_none();
+    match &args.output {
+        Some(path) => fs::write(path, bytes).map_err(|e| format!("{}: {}", path.display(), e))?,
+        None if matches!(args.format, OutputFormat::Ansi | OutputFormat::WorldJson) => {
+            print!("{}", String::from_utf8_lossy(&bytes));
+        }
+        None => return Err("binary formats need an output path, set it with --output".to_string()),
+    }
+
+    if !args.quiet {
+        let statistics = statistics(&saved, &generator);
+        // Keeps the standard output clean when the world is written to it
+        if to_stdout && args.format == OutputFormat::WorldJson {
+            eprint!("{}", statistics);
+        } else {
+            print!("{}", statistics);
+        }
+    }
+    Ok(())
+}
+
+fn encode_height_map(height_map: &HeightMap, format: Format) -> Result<Vec<u8>, String> {
+    io::encode(height_map, format).map_err(|e| e.to_string())
+}
+
+fn statistics(saved: &SavedWorld, generator: &WorldGenerator) -> String {
+    let world = saved.world();
+    let tiles = world.iter().flatten().count();
+    let elevations = world.iter().flatten().map(|tile| tile.elevation);
+    let min_elevation = elevations.clone().min().unwrap_or(0);
+    let max_elevation = elevations.clone().max().unwrap_or(0);
+    let mean_elevation = elevations.sum::<usize>() as f32 / usize::max(tiles, 1) as f32;
+
+    let mut tile_types = BTreeMap::<String, usize>::new();
+    let mut contents = BTreeMap::<String, usize>::new();
+    for tile in world.iter().flatten() {
+        *tile_types
+            .entry(format!("{:?}", tile.tile_type))
+            .or_default() += 1;
+        if tile.content != Content::None {
+            let name = format!("{:?}", tile.content);
+            *contents
+                .entry(name.split('(').next().unwrap_or_default().to_string())
+                .or_default() += 1;
+        }
+    }
+
+    let mut out = format!(
+        "seed: {}\nsize: {}x{}\nspawn: {:?}\nelevation: min {} max {} mean {:.2}\nmax score: {}\n",
+        generator.seed(),
+        world.first().map_or(0, |row| row.len()),
+        world.len(),
+        saved.spawn,
+        min_elevation,
+        max_elevation,
+        mean_elevation,
+        saved.max_score
+    );
+    out.push_str("tile types:\n");
+    for (name, count) in tile_types {
+        out.push_str(&format!(
+            "  {:<14}{:>8} ({:.1}%)\n",
+            name,
+            count,
+            100.0 * count as f32 / tiles as f32
+        ));
+    }
+    out.push_str("contents:\n");
+    for (name, count) in contents {
+        out.push_str(&format!("  {:<14}{:>8}\n", name, count));
+    }
+    if let Some(report) = generator.slope_report() {
+        out.push_str(&format!(
+            "slope: steepest step {} before, {} passes, {} tiles modified by {} in total, {} at most\n",
+            report.steepest_before,
+            report.passes,
+            report.modified,
+            report.total_change,
+            report.max_change
+        ));
+    }
+    if let Some(report) = generator.connectivity_report() {
+        out.push_str(&format!(
+            "connectivity: {:.1}% reachable, {} attempts, {} disconnected regions, {} tiles carved\n",
+            100.0 * report.reachable_fraction,
+            report.attempts,
+            report.disconnected.len(),
+            report.carved.len()
+        ));
+    }
+    out
+}
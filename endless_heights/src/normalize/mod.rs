@@ -0,0 +1,243 @@
+use crate::height::HeightMap;
+
+/// Remapping applied to elevations once they are scaled between 0 and 1.
+#[derive(Clone, Debug, Default)]
+pub enum Curve {
+    /// Leaves elevations untouched.
+    #[default]
+    Linear,
+    /// Raises elevations to the given power. Above 1 flattens the lowlands and sharpens the peaks, below 1 does the opposite.
+    Power(f32),
+    /// Monotone cubic spline through the given (input, output) points.
+    /// Points must lie between 0 and 1, be sorted by input, and be at least two.
+    Spline(Vec<(f32, f32)>),
+}
+
+impl Curve {
+    /// Returns the remapped value of t, between 0 and 1.
+    pub fn apply(&self, t: f32) -> f32 {
+        match self {
+            Curve::Linear => t,
+            Curve::Power(exponent) => t.powf(*exponent),
+            Curve::Spline(points) => spline(points, t),
+        }
+        .clamp(0.0, 1.0)
+    }
+
+    /// Returns why the curve is invalid, if it is.
+    pub fn check(&self) -> Result<(), String> {
+        match self {
+            Curve::Linear => Ok(()),
+            Curve::Power(exponent) => {
+                if exponent.is_finite() && *exponent > 0.0 {
+                    Ok(())
+                } else {
+                    Err(format!("power {} is not a positive number", exponent))
+                }
+            }
+            Curve::Spline(points) => {
+                if points.len() < 2 {
+                    return Err("a spline needs at least two points".to_string());
+                }
+                if points
+                    .iter()
+                    .any(|(x, y)| !(0.0..=1.0).contains(x) || !(0.0..=1.0).contains(y))
+                {
+                    return Err("spline points must lie between 0 and 1".to_string());
+                }
+                if points.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+                    return Err(
+                        "spline points must be sorted by strictly increasing input".to_string()
+                    );
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Parameters of the normalization stage.
+/// Elevations are first scaled between 0 and 1, optionally equalized so that every elevation is about as common, remapped by the curve, and finally scaled to the target range.
+/// - min_elevation: Elevation of the lowest tile.
+/// - max_elevation: Elevation of the highest tile.
+/// - curve: Remapping of the scaled elevations.
+/// - equalize: Histogram equalization, applied before the curve.
+#[derive(Clone, Debug)]
+pub struct NormalizeParams {
+    pub min_elevation: usize,
+    pub max_elevation: usize,
+    pub curve: Curve,
+    pub equalize: bool,
+}
+
+impl Default for NormalizeParams {
+    fn default() -> Self {
+        Self {
+            min_elevation: 0,
+            max_elevation: 40,
+            curve: Curve::Linear,
+            equalize: false,
+        }
+    }
+}
+
+/// Rescales the elevations of the height map as described by the parameters.
+/// A flat map is set to min_elevation everywhere.
+pub fn normalize(height_map: &mut HeightMap, params: &NormalizeParams) {
+    let rows = height_map.rows();
+    let cols = height_map.cols();
+    let elevations = (0..rows)
+        .flat_map(|i| (0..cols).map(move |j| (i, j)))
+        .map(|(i, j)| height_map.elevation(i, j))
+        .collect::<Vec<usize>>();
+    let (Some(&min), Some(&max)) = (elevations.iter().min(), elevations.iter().max()) else {
+        return;
+    };
+
+    // Sorted elevations give the count of tiles at or below any elevation, whatever the range of the map
+    let mut sorted = Vec::new();
+    if params.equalize {
+        sorted = elevations.clone();
+        sorted.sort_unstable();
+    }
+    let cumulative = |elevation: usize| sorted.partition_point(|&e| e <= elevation);
+    let lowest_count = cumulative(min);
+
+    let range = params.max_elevation.saturating_sub(params.min_elevation) as f32;
+    for i in 0..rows {
+        for j in 0..cols {
+            let elevation = height_map.elevation(i, j);
+            let t = if max == min {
+                0.0
+            } else if params.equalize {
+                let below = cumulative(elevation) - lowest_count;
+                below as f32 / (elevations.len() - lowest_count) as f32
+            } else {
+                (elevation - min) as f32 / (max - min) as f32
+            };
+            let remapped = params.curve.apply(t);
+            height_map.set_elevation(
+                i,
+                j,
+                params.min_elevation + (remapped * range).round() as usize,
+            );
+        }
+    }
+}
+
+/// Evaluates the monotone cubic spline through the points at t, using Fritsch-Carlson tangents.
+/// Without points t is left untouched, and a single point is a constant.
+fn spline(points: &[(f32, f32)], t: f32) -> f32 {
+    let n = points.len();
+    match points {
+        [] => return t,
+        [(_, y)] => return *y,
+        _ => {}
+    }
+    if t <= points[0].0 {
+        return points[0].1;
+    }
+    if t >= points[n - 1].0 {
+        return points[n - 1].1;
+    }
+    let slopes = points
+        .windows(2)
+        .map(|pair| (pair[1].1 - pair[0].1) / (pair[1].0 - pair[0].0))
+        .collect::<Vec<f32>>();
+    let mut tangents = Vec::with_capacity(n);
+    tangents.push(slopes[0]);
+    for k in 1..n - 1 {
+        if slopes[k - 1] * slopes[k] <= 0.0 {
+            tangents.push(0.0);
+        } else {
+            tangents.push((slopes[k - 1] + slopes[k]) / 2.0);
+        }
+    }
+    tangents.push(slopes[n - 2]);
+    // Limits the tangents so that the spline does not overshoot
+    for k in 0..n - 1 {
+        if slopes[k] == 0.0 {
+            tangents[k] = 0.0;
+            tangents[k + 1] = 0.0;
+            continue;
+        }
+        let a = tangents[k] / slopes[k];
+        let b = tangents[k + 1] / slopes[k];
+        let length = a * a + b * b;
+        if length > 9.0 {
+            let factor = 3.0 / length.sqrt();
+            tangents[k] = factor * a * slopes[k];
+            tangents[k + 1] = factor * b * slopes[k];
+        }
+    }
+
+    let k = points
+        .windows(2)
+        .position(|pair| t < pair[1].0)
+        .unwrap_or(n - 2);
+    let (x0, y0) = points[k];
+    let (x1, y1) = points[k + 1];
+    let h = x1 - x0;
+    let s = (t - x0) / h;
+    let s2 = s * s;
+    let s3 = s2 * s;
+    (2.0 * s3 - 3.0 * s2 + 1.0) * y0
+        + (s3 - 2.0 * s2 + s) * h * tangents[k]
+        + (-2.0 * s3 + 3.0 * s2) * y1
+        + (s3 - s2) * h * tangents[k + 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rescales_to_the_target_range() {
+        let mut height_map = HeightMap::from_elevations(&[vec![10, 20, 30]]);
+        normalize(
+            &mut height_map,
+            &NormalizeParams {
+                min_elevation: 5,
+                max_elevation: 9,
+                ..Default::default()
+            },
+        );
+        assert_eq!(height_map.elevations(), &[5, 7, 9]);
+    }
+
+    #[test]
+    fn equalization_spreads_clustered_elevations() {
+        let mut height_map = HeightMap::from_elevations(&[vec![0, 1, 2, 3, 4_000_000_000_000]]);
+        normalize(
+            &mut height_map,
+            &NormalizeParams {
+                min_elevation: 0,
+                max_elevation: 4,
+                equalize: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(height_map.elevations(), &[0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn spline_goes_through_its_points_without_overshooting() {
+        let points = [(0.0, 0.0), (0.3, 0.6), (0.6, 0.65), (1.0, 1.0)];
+        for (x, y) in points {
+            assert!((spline(&points, x) - y).abs() < 1e-5);
+        }
+        let mut previous = 0.0;
+        for step in 0..=100 {
+            let value = spline(&points, step as f32 / 100.0);
+            assert!(value >= previous - 1e-6);
+            previous = value;
+        }
+    }
+
+    #[test]
+    fn spline_without_enough_points_does_not_panic() {
+        assert_eq!(Curve::Spline(Vec::new()).apply(0.3), 0.3);
+        assert_eq!(Curve::Spline(vec![(0.5, 0.2)]).apply(0.9), 0.2);
+        assert!(Curve::Spline(Vec::new()).check().is_err());
+    }
+}
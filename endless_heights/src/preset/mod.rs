@@ -0,0 +1,293 @@
+use crate::biome::BiomeThresholds;
+use crate::erosion::{ErosionParams, HydraulicErosionParams, ThermalErosionParams};
+use crate::height::kernel::{KernelKind, KernelMix};
+use crate::height::BlendMode;
+use crate::normalize::{Curve, NormalizeParams};
+use crate::utils::Dimension;
+use crate::weather::Climate;
+
+/// Named terrains, each a tuned combination of generator parameters.
+/// Presets are tuned for a 100x100 map: the amount of mountains grows with the area of the map and the variances with its side.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    /// Wide, low swells of grass with a few ponds.
+    Plains,
+    /// Many soft hills merging into each other.
+    RollingHills,
+    /// Steep, snowy peaks carved by erosion.
+    Alpine,
+    /// Small islands scattered over the sea.
+    Archipelago,
+    /// Arid, terraced mesas cut by gorges.
+    Canyonlands,
+    /// A single large peak rising from the lowlands.
+    SingleVolcano,
+}
+
+/// The generator parameters set by a preset.
+/// See `WorldGenerator` for the meaning of each parameter.
+#[derive(Clone, Debug)]
+pub struct PresetParams {
+    pub amount_mountains: usize,
+    pub amount_ridges: usize,
+    pub kernels: KernelMix,
+    pub scale: f32,
+    pub interpolation: f32,
+    pub max_variance: f32,
+    pub min_variance: f32,
+    pub blend: BlendMode,
+    pub erosion: Option<ErosionParams>,
+    pub normalize: Option<NormalizeParams>,
+    pub biomes: BiomeThresholds,
+    pub climate: Climate,
+}
+
+impl Preset {
+    /// Returns the parameters of the preset for a map of the given dimension.
+    pub fn params(&self, dimension: &Dimension) -> PresetParams {
+        let side = f32::sqrt((dimension.width * dimension.height) as f32) / 100.0;
+        let area = side * side;
+        let mountains = |amount: f32| usize::max((amount * area).round() as usize, 1);
+        let ridges = |amount: f32| (amount * area).round() as usize;
+        let hydraulic = || HydraulicErosionParams {
+            iterations: usize::max((5000.0 * area) as usize, 1),
+            ..Default::default()
+        };
+
+        match self {
+            Preset::Plains => PresetParams {
+                amount_mountains: mountains(12.0),
+                amount_ridges: ridges(0.0),
+                kernels: KernelMix::default(),
+                scale: 6.0,
+                interpolation: 0.3,
+                max_variance: 30.0 * side,
+                min_variance: 15.0 * side,
+                blend: BlendMode::Weighted,
+                erosion: Some(ErosionParams {
+                    thermal: Some(ThermalErosionParams::default()),
+                    hydraulic: None,
+                }),
+                normalize: Some(NormalizeParams {
+                    min_elevation: 0,
+                    max_elevation: 10,
+                    ..Default::default()
+                }),
+                biomes: BiomeThresholds {
+                    deep_water: 0.02,
+                    shallow_water: 0.05,
+                    sand: 0.08,
+                    grass: 0.8,
+                    hill: 1.0,
+                    mountain: 1.0,
+                },
+                climate: Climate::Temperate,
+            },
+            Preset::RollingHills => PresetParams {
+                amount_mountains: mountains(40.0),
+                amount_ridges: ridges(0.0),
+                kernels: KernelMix {
+                    gaussian: 0.7,
+                    terraced_hill: 0.3,
+                    ..KernelMix::only(KernelKind::Gaussian)
+                },
+                scale: 12.0,
+                interpolation: 0.2,
+                max_variance: 12.0 * side,
+                min_variance: 6.0 * side,
+                blend: BlendMode::SmoothMax { sharpness: 0.5 },
+                erosion: Some(ErosionParams {
+                    thermal: Some(ThermalErosionParams::default()),
+                    hydraulic: None,
+                }),
+                // Lowlands stay above the sea
+                normalize: Some(NormalizeParams {
+                    min_elevation: 4,
+                    max_elevation: 20,
+                    curve: Curve::Power(0.8),
+                    equalize: true,
+                }),
+                biomes: BiomeThresholds {
+                    deep_water: 0.02,
+                    shallow_water: 0.06,
+                    sand: 0.1,
+                    grass: 0.55,
+                    hill: 0.9,
+                    mountain: 1.0,
+                },
+                climate: Climate::Temperate,
+            },
+            Preset::Alpine => PresetParams {
+                amount_mountains: mountains(30.0),
+                amount_ridges: ridges(4.0),
+                kernels: KernelMix::default(),
+                scale: 40.0,
+                interpolation: 0.1,
+                max_variance: 14.0 * side,
+                min_variance: 4.0 * side,
+                blend: BlendMode::Max,
+                erosion: Some(ErosionParams {
+                    thermal: Some(ThermalErosionParams::default()),
+                    hydraulic: Some(hydraulic()),
+                }),
+                normalize: Some(NormalizeParams {
+                    min_elevation: 0,
+                    max_elevation: 60,
+                    curve: Curve::Power(1.4),
+                    equalize: false,
+                }),
+                biomes: BiomeThresholds {
+                    deep_water: 0.02,
+                    shallow_water: 0.04,
+                    sand: 0.06,
+                    grass: 0.2,
+                    hill: 0.4,
+                    mountain: 0.65,
+                },
+                climate: Climate::Alpine,
+            },
+            Preset::Archipelago => PresetParams {
+                amount_mountains: mountains(25.0),
+                amount_ridges: ridges(2.0),
+                kernels: KernelMix::default(),
+                scale: 20.0,
+                interpolation: 0.05,
+                max_variance: 9.0 * side,
+                min_variance: 3.0 * side,
+                blend: BlendMode::Max,
+                erosion: None,
+                normalize: Some(NormalizeParams {
+                    min_elevation: 0,
+                    max_elevation: 30,
+                    curve: Curve::Power(1.3),
+                    equalize: false,
+                }),
+                biomes: BiomeThresholds {
+                    deep_water: 0.15,
+                    shallow_water: 0.3,
+                    sand: 0.36,
+                    grass: 0.65,
+                    hill: 0.82,
+                    mountain: 0.94,
+                },
+                climate: Climate::Tropical,
+            },
+            Preset::Canyonlands => PresetParams {
+                amount_mountains: mountains(20.0),
+                amount_ridges: ridges(0.0),
+                kernels: KernelMix {
+                    gaussian: 0.3,
+                    plateau: 0.3,
+                    mesa: 0.4,
+                    ..KernelMix::only(KernelKind::Gaussian)
+                },
+                scale: 25.0,
+                interpolation: 0.4,
+                max_variance: 25.0 * side,
+                min_variance: 10.0 * side,
+                blend: BlendMode::Sum,
+                erosion: Some(ErosionParams {
+                    thermal: None,
+                    hydraulic: Some(hydraulic()),
+                }),
+                // Flat steps joined by steep walls
+                normalize: Some(NormalizeParams {
+                    min_elevation: 0,
+                    max_elevation: 40,
+                    curve: Curve::Spline(vec![
+                        (0.0, 0.0),
+                        (0.2, 0.1),
+                        (0.3, 0.45),
+                        (0.55, 0.5),
+                        (0.65, 0.85),
+                        (1.0, 0.9),
+                    ]),
+                    equalize: true,
+                }),
+                biomes: BiomeThresholds {
+                    deep_water: 0.02,
+                    shallow_water: 0.04,
+                    sand: 0.5,
+                    grass: 0.6,
+                    hill: 0.85,
+                    mountain: 1.0,
+                },
+                climate: Climate::Arid,
+            },
+            Preset::SingleVolcano => PresetParams {
+                amount_mountains: 1,
+                amount_ridges: 0,
+                kernels: KernelMix::only(KernelKind::Volcano),
+                scale: 50.0,
+                interpolation: 0.0,
+                max_variance: 30.0 * side,
+                min_variance: 25.0 * side,
+                blend: BlendMode::Max,
+                erosion: Some(ErosionParams {
+                    thermal: None,
+                    hydraulic: Some(hydraulic()),
+                }),
+                // Lowlands stay above the sea
+                normalize: Some(NormalizeParams {
+                    min_elevation: 12,
+                    max_elevation: 50,
+                    curve: Curve::Power(1.2),
+                    equalize: false,
+                }),
+                biomes: BiomeThresholds {
+                    deep_water: 0.03,
+                    shallow_water: 0.08,
+                    sand: 0.2,
+                    ..Default::default()
+                },
+                climate: Climate::Temperate,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRESETS: [Preset; 6] = [
+        Preset::Plains,
+        Preset::RollingHills,
+        Preset::Alpine,
+        Preset::Archipelago,
+        Preset::Canyonlands,
+        Preset::SingleVolcano,
+    ];
+
+    #[test]
+    fn presets_scale_with_the_map() {
+        for preset in PRESETS {
+            let small = preset.params(&Dimension {
+                width: 4,
+                height: 4,
+            });
+            let tuned = preset.params(&Dimension {
+                width: 100,
+                height: 100,
+            });
+            let large = preset.params(&Dimension {
+                width: 400,
+                height: 100,
+            });
+            for params in [&small, &tuned, &large] {
+                assert!(params.amount_mountains >= 1, "{:?}", preset);
+                assert!(
+                    0.0 < params.min_variance && params.min_variance < params.max_variance,
+                    "{:?}",
+                    preset
+                );
+                if let Some(erosion) = &params.erosion {
+                    assert_eq!(erosion.check(), Ok(()), "{:?}", preset);
+                }
+            }
+            assert!(small.amount_mountains <= tuned.amount_mountains);
+            assert!(tuned.amount_mountains <= large.amount_mountains);
+            assert_eq!(large.max_variance, 2.0 * tuned.max_variance);
+        }
+    }
+}
@@ -0,0 +1,373 @@
+use std::fmt::Write;
+
+use robotics_lib::world::tile::{Content, TileType};
+
+use crate::height::HeightMap;
+use crate::World;
+
+const RESET: &str = "\x1b[0m";
+
+/// Options of the terminal renderer.
+/// - shade: Darkens low tiles and brightens high ones.
+/// - legend: Appends the glyphs and colors used to the drawing.
+/// - ruler: Draws row and column coordinates around the map.
+/// - marker: A tile to highlight with '@', for instance the spawn point.
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    pub shade: bool,
+    pub legend: bool,
+    pub ruler: bool,
+    pub marker: Option<(usize, usize)>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            shade: true,
+            legend: true,
+            ruler: true,
+            marker: None,
+        }
+    }
+}
+
+/// Draws the world for a truecolor terminal. Each tile takes two characters.
+/// Tiles are colored by type and show their content glyph, or their type glyph when empty.
+pub fn render_world(world: &World, options: &RenderOptions) -> String {
+    let rows = world.len();
+    let cols = world.first().map_or(0, |row| row.len());
+    let (min_elevation, max_elevation) =
+        elevation_range(world.iter().flatten().map(|tile| tile.elevation));
+
+    let mut out = String::new();
+    if options.ruler {
+        column_ruler(&mut out, rows, cols);
+    }
+    for (i, row) in world.iter().enumerate() {
+        if options.ruler {
+            row_ruler(&mut out, rows, i);
+        }
+        for (j, tile) in row.iter().enumerate() {
+            let base = tile_color(&tile.tile_type);
+            let color = if options.shade {
+                shade(base, tile.elevation, min_elevation, max_elevation)
+            } else {
+                base
+            };
+            let glyph = if options.marker == Some((i, j)) {
+                '@'
+            } else if tile.content != Content::None {
+                content_glyph(&tile.content)
+            } else {
+                tile_glyph(&tile.tile_type)
+            };
+            cell(&mut out, color, glyph);
+        }
+        out.push_str(RESET);
+        out.push('\n');
+    }
+
+    if options.legend {
+        let mut tile_types = Vec::<TileType>::new();
+        let mut contents = Vec::<Content>::new();
+        for tile in world.iter().flatten() {
+            if !tile_types.contains(&tile.tile_type) {
+                tile_types.push(tile.tile_type.clone());
+            }
+            if tile.content != Content::None
+                && !contents
+                    .iter()
+                    .any(|content| content_glyph(content) == content_glyph(&tile.content))
+            {
+                contents.push(tile.content.clone());
+            }
+        }
+        out.push('\n');
+        for tile_type in tile_types {
+            cell(&mut out, tile_color(&tile_type), tile_glyph(&tile_type));
+            let _ = writeln!(out, "{} {:?}", RESET, tile_type);
+        }
+        for content in contents {
+            let _ = writeln!(
+                out,
+                "{}  {}",
+                content_glyph(&content),
+                format!("{:?}", content)
+                    .split('(')
+                    .next()
+                    .unwrap_or_default()
+            );
+        }
+        if options.marker.is_some() {
+            let _ = writeln!(out, "@  Marker");
+        }
+        let _ = writeln!(out, "Elevation {} to {}", min_elevation, max_elevation);
+    }
+    out
+}
+
+/// Draws the height map for a truecolor terminal, in shades of gray from black (lowest) to white (highest).
+pub fn render_height_map(height_map: &HeightMap, options: &RenderOptions) -> String {
+    let rows = height_map.rows();
+    let cols = height_map.cols();
+    let (min_elevation, max_elevation) =
+        elevation_range((0..rows).flat_map(|i| (0..cols).map(move |j| height_map.elevation(i, j))));
+
+    let mut out = String::new();
+    if options.ruler {
+        column_ruler(&mut out, rows, cols);
+    }
+    for i in 0..rows {
+        if options.ruler {
+            row_ruler(&mut out, rows, i);
+        }
+        for j in 0..cols {
+            let level =
+                (255.0 * normalize(height_map.elevation(i, j), min_elevation, max_elevation)) as u8;
+            let glyph = if options.marker == Some((i, j)) {
+                '@'
+            } else {
+                ' '
+            };
+            cell(&mut out, (level, level, level), glyph);
+        }
+        out.push_str(RESET);
+        out.push('\n');
+    }
+
+    if options.legend {
+        out.push('\n');
+        for step in 0..=4 {
+            let t = step as f32 / 4.0;
+            let level = (255.0 * t) as u8;
+            cell(&mut out, (level, level, level), ' ');
+            let elevation = min_elevation as f32 + t * (max_elevation - min_elevation) as f32;
+            let _ = writeln!(out, "{} {:.0}", RESET, elevation);
+        }
+    }
+    out
+}
+
+/// Returns the color tiles of the given type are drawn with.
+pub fn tile_color(tile_type: &TileType) -> (u8, u8, u8) {
+    match tile_type {
+        TileType::DeepWater => (16, 52, 128),
+        TileType::ShallowWater => (48, 112, 200),
+        TileType::Sand => (222, 200, 140),
+        TileType::Grass => (84, 160, 64),
+        TileType::Street => (110, 110, 110),
+        TileType::Hill => (140, 150, 70),
+        TileType::Mountain => (130, 110, 90),
+        TileType::Snow => (240, 240, 250),
+        TileType::Lava => (220, 60, 20),
+        TileType::Teleport(_) => (170, 70, 200),
+        TileType::Wall => (60, 50, 50),
+    }
+}
+
+/// Returns the glyph drawn on empty tiles of the given type.
+pub fn tile_glyph(tile_type: &TileType) -> char {
+    match tile_type {
+        TileType::DeepWater => '≈',
+        TileType::ShallowWater => '~',
+        TileType::Sand => '.',
+        TileType::Grass => ',',
+        TileType::Street => '=',
+        TileType::Hill => 'n',
+        TileType::Mountain => '^',
+        TileType::Snow => '*',
+        TileType::Lava => '!',
+        TileType::Teleport(_) => 'O',
+        TileType::Wall => '#',
+    }
+}
+
+/// Returns the glyph drawn on tiles holding the given content.
+pub fn content_glyph(content: &Content) -> char {
+    match content {
+        Content::Rock(_) => 'o',
+        Content::Tree(_) => '♣',
+        Content::Garbage(_) => '%',
+        Content::Fire => 'f',
+        Content::Coin(_) => '$',
+        Content::Bin(_) => 'u',
+        Content::Crate(_) => 'c',
+        Content::Bank(_) => 'B',
+        Content::Water(_) => 'w',
+        Content::Market(_) => 'M',
+        Content::Fish(_) => '<',
+        Content::Building => 'H',
+        Content::Bush(_) => '"',
+        Content::JollyBlock(_) => '?',
+        Content::Scarecrow => '&',
+        Content::None => ' ',
+    }
+}
+
+/// Returns the lowest and highest elevations, or (0, 0) if there are none.
+fn elevation_range(elevations: impl Iterator<Item = usize>) -> (usize, usize) {
+    elevations
+        .fold(None, |range, elevation| match range {
+            None => Some((elevation, elevation)),
+            Some((min, max)) => Some((usize::min(min, elevation), usize::max(max, elevation))),
+        })
+        .unwrap_or((0, 0))
+}
+
+fn normalize(elevation: usize, min_elevation: usize, max_elevation: usize) -> f32 {
+    if max_elevation <= min_elevation {
+        return 0.5;
+    }
+    (elevation - min_elevation) as f32 / (max_elevation - min_elevation) as f32
+}
+
+/// Scales a color from 60% brightness on the lowest tiles to 120% on the highest.
+fn shade(
+    (r, g, b): (u8, u8, u8),
+    elevation: usize,
+    min_elevation: usize,
+    max_elevation: usize,
+) -> (u8, u8, u8) {
+    let factor = 0.6 + 0.6 * normalize(elevation, min_elevation, max_elevation);
+    let scale = |channel: u8| (channel as f32 * factor).min(255.0) as u8;
+    (scale(r), scale(g), scale(b))
+}
+
+/// Writes a two characters wide cell with the given background, and a contrasting glyph.
+fn cell(out: &mut String, (r, g, b): (u8, u8, u8), glyph: char) {
+    let luminance = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+    let foreground = if luminance > 128.0 { 0 } else { 255 };
+    let _ = write!(
+        out,
+        "\x1b[48;2;{};{};{}m\x1b[38;2;{f};{f};{f}m{} ",
+        r,
+        g,
+        b,
+        glyph,
+        f = foreground
+    );
+}
+
+fn row_label_width(rows: usize) -> usize {
+    rows.saturating_sub(1).to_string().len()
+}
+
+/// Writes the column numbers, vertically, every 5 columns.
+fn column_ruler(out: &mut String, rows: usize, cols: usize) {
+    let digits = cols.saturating_sub(1).to_string().len();
+    for digit in 0..digits {
+        out.push_str(&" ".repeat(row_label_width(rows) + 1));
+        for j in 0..cols {
+            let label = format!("{:>width$}", j, width = digits);
+            let character = if j % 5 == 0 {
+                label.chars().nth(digit).unwrap_or(' ')
+            } else {
+                ' '
+            };
+            out.push(character);
+            out.push(' ');
+        }
+        out.push('\n');
+    }
+}
+
+fn row_ruler(out: &mut String, rows: usize, i: usize) {
+    let _ = write!(out, "{:>width$} ", i, width = row_label_width(rows));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use robotics_lib::world::tile::Tile;
+
+    fn world() -> World {
+        vec![
+            vec![
+                Tile {
+                    tile_type: TileType::Grass,
+                    content: Content::Tree(2),
+                    elevation: 3,
+                },
+                Tile {
+                    tile_type: TileType::ShallowWater,
+                    content: Content::None,
+                    elevation: 0,
+                },
+            ],
+            vec![
+                Tile {
+                    tile_type: TileType::Mountain,
+                    content: Content::None,
+                    elevation: 9,
+                };
+                2
+            ],
+        ]
+    }
+
+    /// Drops the escape sequences, keeping the glyphs.
+    fn glyphs(rendered: &str) -> String {
+        let mut out = String::new();
+        let mut escaped = false;
+        for c in rendered.chars() {
+            match c {
+                '\x1b' => escaped = true,
+                'm' if escaped => escaped = false,
+                _ if !escaped => out.push(c),
+                _ => {}
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn world_shows_contents_types_and_marker() {
+        let options = RenderOptions {
+            legend: false,
+            ruler: false,
+            marker: Some((1, 1)),
+            ..Default::default()
+        };
+        assert_eq!(glyphs(&render_world(&world(), &options)), "♣ ~ \n^ @ \n");
+    }
+
+    #[test]
+    fn empty_maps_render_without_panicking() {
+        let options = RenderOptions::default();
+        assert!(render_world(&Vec::new(), &options).contains("Elevation 0 to 0"));
+        let rendered = render_height_map(&HeightMap::from_elevations(&[]), &options);
+        assert!(glyphs(&rendered).contains(" 0\n"));
+    }
+
+    #[test]
+    fn legend_lists_what_is_drawn() {
+        let options = RenderOptions {
+            ruler: false,
+            ..Default::default()
+        };
+        let legend = glyphs(&render_world(&world(), &options));
+        for line in [
+            "Grass",
+            "ShallowWater",
+            "Mountain",
+            "♣  Tree",
+            "Elevation 0 to 9",
+        ] {
+            assert!(legend.contains(line), "{} missing from\n{}", line, legend);
+        }
+        assert!(!legend.contains("Marker"));
+    }
+
+    #[test]
+    fn height_map_is_drawn_in_grays() {
+        let height_map = HeightMap::from_elevations(&[vec![0, 10]]);
+        let options = RenderOptions {
+            legend: false,
+            ruler: false,
+            ..Default::default()
+        };
+        let rendered = render_height_map(&height_map, &options);
+        assert!(rendered.contains("\x1b[48;2;0;0;0m"));
+        assert!(rendered.contains("\x1b[48;2;255;255;255m"));
+    }
+}
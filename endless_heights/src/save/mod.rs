@@ -0,0 +1,599 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use robotics_lib::world::environmental_conditions::{EnvironmentalConditions, WeatherType};
+use robotics_lib::world::tile::{Content, Tile, TileType};
+use robotics_lib::world::world_generator::Generator;
+use serde::{Deserialize, Serialize};
+
+use crate::weather::WeatherConfig;
+use crate::{World, WorldGenerator};
+
+/// Version of the save format written by this crate. Files with a newer version are rejected.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Bytes starting every binary save file, followed by the format version as a little-endian u32.
+const BINARY_MAGIC: &[u8; 4] = b"EHWB";
+
+/// Formats worlds can be saved to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SaveFormat {
+    /// Human readable JSON, meant for debugging.
+    Json,
+    /// Compact binary encoding, meant for large maps.
+    Binary,
+}
+
+/// Errors returned when saving or loading worlds.
+#[derive(Debug)]
+pub enum SaveError {
+    /// The file could not be read or written
+    Io(std::io::Error),
+    /// The JSON encoder or decoder failed
+    Json(serde_json::Error),
+    /// The binary encoder or decoder failed
+    Binary(bincode::Error),
+    /// The file was written by a newer version of the format
+    UnsupportedVersion(u32),
+    /// The file content is not a valid world
+    InvalidData(String),
+}
+
+impl Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveError::Io(error) => write!(f, "io error: {}", error),
+            SaveError::Json(error) => write!(f, "json error: {}", error),
+            SaveError::Binary(error) => write!(f, "binary error: {}", error),
+            SaveError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported format version {}, the latest supported is {}",
+                version, FORMAT_VERSION
+            ),
+            SaveError::InvalidData(message) => write!(f, "invalid data: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<std::io::Error> for SaveError {
+    fn from(error: std::io::Error) -> Self {
+        SaveError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(error: serde_json::Error) -> Self {
+        SaveError::Json(error)
+    }
+}
+
+impl From<bincode::Error> for SaveError {
+    fn from(error: bincode::Error) -> Self {
+        SaveError::Binary(error)
+    }
+}
+
+/// Everything a generator returns, in a serializable form.
+/// - seed: The seed the world was generated from, if known.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedWorld {
+    pub version: u32,
+    pub seed: Option<u64>,
+    pub tiles: Vec<Vec<SavedTile>>,
+    pub spawn: (usize, usize),
+    pub weather: SavedWeather,
+    pub max_score: f32,
+    pub score_table: Option<Vec<(SavedContent, f32)>>,
+}
+
+impl SavedWorld {
+    /// Creates a saved world from the output of a generator.
+    /// The environmental conditions cannot be read back from robotics_lib, so the weather configuration they were built from is needed.
+    pub fn new(
+        world: &World,
+        spawn: (usize, usize),
+        weather: &WeatherConfig,
+        max_score: f32,
+        score_table: Option<&HashMap<Content, f32>>,
+    ) -> SavedWorld {
+        SavedWorld {
+            version: FORMAT_VERSION,
+            seed: None,
+            tiles: world
+                .iter()
+                .map(|row| row.iter().map(SavedTile::from).collect())
+                .collect(),
+            spawn,
+            weather: SavedWeather::from(weather),
+            max_score,
+            score_table: score_table.map(|table| {
+                table
+                    .iter()
+                    .map(|(content, score)| (SavedContent::from(content), *score))
+                    .collect()
+            }),
+        }
+    }
+
+    /// Runs the generator and saves its output, seed included.
+    pub fn generate(generator: &mut WorldGenerator) -> SavedWorld {
+        let (world, spawn, _, max_score, score_table) = generator.gen();
+        SavedWorld {
+            seed: Some(generator.seed()),
+            ..SavedWorld::new(
+                &world,
+                spawn,
+                generator.weather(),
+                max_score,
+                score_table.as_ref(),
+            )
+        }
+    }
+
+    /// Returns the saved tiles as a world.
+    pub fn world(&self) -> World {
+        self.tiles
+            .iter()
+            .map(|row| row.iter().map(Tile::from).collect())
+            .collect()
+    }
+
+    /// Returns the saved weather configuration.
+    pub fn weather(&self) -> WeatherConfig {
+        WeatherConfig::from(&self.weather)
+    }
+
+    /// Returns the saved score table.
+    pub fn score_table(&self) -> Option<HashMap<Content, f32>> {
+        self.score_table.as_ref().map(|table| {
+            table
+                .iter()
+                .map(|(content, score)| (Content::from(content), *score))
+                .collect()
+        })
+    }
+
+    /// Encodes the world in the given format.
+    pub fn encode(&self, format: SaveFormat) -> Result<Vec<u8>, SaveError> {
+        match format {
+            SaveFormat::Json => Ok(serde_json::to_vec_pretty(self)?),
+            SaveFormat::Binary => {
+                let mut out = BINARY_MAGIC.to_vec();
+                out.extend(self.version.to_le_bytes());
+                out.extend(bincode::serialize(self)?);
+                Ok(out)
+            }
+        }
+    }
+
+    /// Decodes a world in either format, checking its version and content.
+    pub fn decode(bytes: &[u8]) -> Result<SavedWorld, SaveError> {
+        let saved = if bytes.starts_with(BINARY_MAGIC) {
+            let header_length = BINARY_MAGIC.len() + 4;
+            if bytes.len() < header_length {
+                return Err(SaveError::InvalidData("truncated header".to_string()));
+            }
+            let mut version = [0; 4];
+            version.copy_from_slice(&bytes[BINARY_MAGIC.len()..header_length]);
+            let version = u32::from_le_bytes(version);
+            if version > FORMAT_VERSION {
+                return Err(SaveError::UnsupportedVersion(version));
+            }
+            bincode::deserialize::<SavedWorld>(&bytes[header_length..])?
+        } else {
+            // The version is checked first, so newer files are reported as such instead of failing to parse
+            #[derive(Deserialize)]
+            struct Versioned {
+                version: u32,
+            }
+            let versioned = serde_json::from_slice::<Versioned>(bytes)?;
+            if versioned.version > FORMAT_VERSION {
+                return Err(SaveError::UnsupportedVersion(versioned.version));
+            }
+            serde_json::from_slice::<SavedWorld>(bytes)?
+        };
+        saved.validate()?;
+        Ok(saved)
+    }
+
+    /// Writes the world to a file in the given format.
+    pub fn save<P: AsRef<Path>>(&self, path: P, format: SaveFormat) -> Result<(), SaveError> {
+        fs::write(path, self.encode(format)?)?;
+        Ok(())
+    }
+
+    /// Reads a world from a file in either format.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SavedWorld, SaveError> {
+        SavedWorld::decode(&fs::read(path)?)
+    }
+
+    fn validate(&self) -> Result<(), SaveError> {
+        if self.version > FORMAT_VERSION {
+            return Err(SaveError::UnsupportedVersion(self.version));
+        }
+        let cols = self.tiles.first().map_or(0, |row| row.len());
+        if self.tiles.iter().any(|row| row.len() != cols) {
+            return Err(SaveError::InvalidData(
+                "rows of different lengths".to_string(),
+            ));
+        }
+        if self.spawn.0 >= self.tiles.len() || self.spawn.1 >= cols {
+            return Err(SaveError::InvalidData(format!(
+                "spawn {:?} outside of the world",
+                self.spawn
+            )));
+        }
+        self.weather()
+            .conditions()
+            .map_err(SaveError::InvalidData)?;
+        Ok(())
+    }
+}
+
+/// A generator replaying a saved world.
+pub struct WorldLoader {
+    saved: SavedWorld,
+    conditions: EnvironmentalConditions,
+}
+
+impl WorldLoader {
+    /// Creates a loader replaying the given world.
+    /// Fails with `SaveError::InvalidData` if robotics_lib refuses the saved weather configuration.
+    pub fn new(saved: SavedWorld) -> Result<WorldLoader, SaveError> {
+        let conditions = saved
+            .weather()
+            .conditions()
+            .map_err(SaveError::InvalidData)?;
+        Ok(WorldLoader { saved, conditions })
+    }
+
+    /// Creates a loader replaying the world saved in the given file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<WorldLoader, SaveError> {
+        WorldLoader::new(SavedWorld::load(path)?)
+    }
+
+    /// Returns the replayed world.
+    pub fn saved(&self) -> &SavedWorld {
+        &self.saved
+    }
+}
+
+impl Generator for WorldLoader {
+    fn gen(
+        &mut self,
+    ) -> (
+        World,
+        (usize, usize),
+        EnvironmentalConditions,
+        f32,
+        Option<HashMap<Content, f32>>,
+    ) {
+        (
+            self.saved.world(),
+            self.saved.spawn,
+            self.conditions.clone(),
+            self.saved.max_score,
+            self.saved.score_table(),
+        )
+    }
+}
+
+/// Serializable copy of a Tile.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedTile {
+    pub tile_type: SavedTileType,
+    pub content: SavedContent,
+    pub elevation: usize,
+}
+
+impl From<&Tile> for SavedTile {
+    fn from(tile: &Tile) -> Self {
+        SavedTile {
+            tile_type: SavedTileType::from(&tile.tile_type),
+            content: SavedContent::from(&tile.content),
+            elevation: tile.elevation,
+        }
+    }
+}
+
+impl From<&SavedTile> for Tile {
+    fn from(tile: &SavedTile) -> Self {
+        Tile {
+            tile_type: TileType::from(&tile.tile_type),
+            content: Content::from(&tile.content),
+            elevation: tile.elevation,
+        }
+    }
+}
+
+/// Serializable copy of a TileType.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum SavedTileType {
+    DeepWater,
+    ShallowWater,
+    Sand,
+    Grass,
+    Street,
+    Hill,
+    Mountain,
+    Snow,
+    Lava,
+    Teleport(bool),
+    Wall,
+}
+
+impl From<&TileType> for SavedTileType {
+    fn from(tile_type: &TileType) -> Self {
+        match tile_type {
+            TileType::DeepWater => SavedTileType::DeepWater,
+            TileType::ShallowWater => SavedTileType::ShallowWater,
+            TileType::Sand => SavedTileType::Sand,
+            TileType::Grass => SavedTileType::Grass,
+            TileType::Street => SavedTileType::Street,
+            TileType::Hill => SavedTileType::Hill,
+            TileType::Mountain => SavedTileType::Mountain,
+            TileType::Snow => SavedTileType::Snow,
+            TileType::Lava => SavedTileType::Lava,
+            TileType::Teleport(unlocked) => SavedTileType::Teleport(*unlocked),
+            TileType::Wall => SavedTileType::Wall,
+        }
+    }
+}
+
+impl From<&SavedTileType> for TileType {
+    fn from(tile_type: &SavedTileType) -> Self {
+        match tile_type {
+            SavedTileType::DeepWater => TileType::DeepWater,
+            SavedTileType::ShallowWater => TileType::ShallowWater,
+            SavedTileType::Sand => TileType::Sand,
+            SavedTileType::Grass => TileType::Grass,
+            SavedTileType::Street => TileType::Street,
+            SavedTileType::Hill => TileType::Hill,
+            SavedTileType::Mountain => TileType::Mountain,
+            SavedTileType::Snow => TileType::Snow,
+            SavedTileType::Lava => TileType::Lava,
+            SavedTileType::Teleport(unlocked) => TileType::Teleport(*unlocked),
+            SavedTileType::Wall => TileType::Wall,
+        }
+    }
+}
+
+/// Serializable copy of a Content.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum SavedContent {
+    Rock(usize),
+    Tree(usize),
+    Garbage(usize),
+    Fire,
+    Coin(usize),
+    Bin(Range<usize>),
+    Crate(Range<usize>),
+    Bank(Range<usize>),
+    Water(usize),
+    Market(usize),
+    Fish(usize),
+    Building,
+    Bush(usize),
+    JollyBlock(usize),
+    Scarecrow,
+    None,
+}
+
+impl From<&Content> for SavedContent {
+    fn from(content: &Content) -> Self {
+        match content {
+            Content::Rock(quantity) => SavedContent::Rock(*quantity),
+            Content::Tree(quantity) => SavedContent::Tree(*quantity),
+            Content::Garbage(quantity) => SavedContent::Garbage(*quantity),
+            Content::Fire => SavedContent::Fire,
+            Content::Coin(quantity) => SavedContent::Coin(*quantity),
+            Content::Bin(range) => SavedContent::Bin(range.clone()),
+            Content::Crate(range) => SavedContent::Crate(range.clone()),
+            Content::Bank(range) => SavedContent::Bank(range.clone()),
+            Content::Water(quantity) => SavedContent::Water(*quantity),
+            Content::Market(quantity) => SavedContent::Market(*quantity),
+            Content::Fish(quantity) => SavedContent::Fish(*quantity),
+            Content::Building => SavedContent::Building,
+            Content::Bush(quantity) => SavedContent::Bush(*quantity),
+            Content::JollyBlock(quantity) => SavedContent::JollyBlock(*quantity),
+            Content::Scarecrow => SavedContent::Scarecrow,
+            Content::None => SavedContent::None,
+        }
+    }
+}
+
+impl From<&SavedContent> for Content {
+    fn from(content: &SavedContent) -> Self {
+        match content {
+            SavedContent::Rock(quantity) => Content::Rock(*quantity),
+            SavedContent::Tree(quantity) => Content::Tree(*quantity),
+            SavedContent::Garbage(quantity) => Content::Garbage(*quantity),
+            SavedContent::Fire => Content::Fire,
+            SavedContent::Coin(quantity) => Content::Coin(*quantity),
+            SavedContent::Bin(range) => Content::Bin(range.clone()),
+            SavedContent::Crate(range) => Content::Crate(range.clone()),
+            SavedContent::Bank(range) => Content::Bank(range.clone()),
+            SavedContent::Water(quantity) => Content::Water(*quantity),
+            SavedContent::Market(quantity) => Content::Market(*quantity),
+            SavedContent::Fish(quantity) => Content::Fish(*quantity),
+            SavedContent::Building => Content::Building,
+            SavedContent::Bush(quantity) => Content::Bush(*quantity),
+            SavedContent::JollyBlock(quantity) => Content::JollyBlock(*quantity),
+            SavedContent::Scarecrow => Content::Scarecrow,
+            SavedContent::None => Content::None,
+        }
+    }
+}
+
+/// Serializable copy of a WeatherConfig.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedWeather {
+    pub forecast: Vec<SavedWeatherType>,
+    pub time_progression_minutes: u8,
+    pub starting_hour: u8,
+}
+
+impl From<&WeatherConfig> for SavedWeather {
+    fn from(weather: &WeatherConfig) -> Self {
+        SavedWeather {
+            forecast: weather
+                .forecast
+                .iter()
+                .map(SavedWeatherType::from)
+                .collect(),
+            time_progression_minutes: weather.time_progression_minutes,
+            starting_hour: weather.starting_hour,
+        }
+    }
+}
+
+impl From<&SavedWeather> for WeatherConfig {
+    fn from(weather: &SavedWeather) -> Self {
+        WeatherConfig {
+            forecast: weather.forecast.iter().map(WeatherType::from).collect(),
+            time_progression_minutes: weather.time_progression_minutes,
+            starting_hour: weather.starting_hour,
+        }
+    }
+}
+
+/// Serializable copy of a WeatherType.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum SavedWeatherType {
+    Sunny,
+    Rainy,
+    Foggy,
+    TropicalMonsoon,
+    TrentinoSnow,
+}
+
+impl From<&WeatherType> for SavedWeatherType {
+    fn from(weather_type: &WeatherType) -> Self {
+        match weather_type {
+            WeatherType::Sunny => SavedWeatherType::Sunny,
+            WeatherType::Rainy => SavedWeatherType::Rainy,
+            WeatherType::Foggy => SavedWeatherType::Foggy,
+            WeatherType::TropicalMonsoon => SavedWeatherType::TropicalMonsoon,
+            WeatherType::TrentinoSnow => SavedWeatherType::TrentinoSnow,
+        }
+    }
+}
+
+impl From<&SavedWeatherType> for WeatherType {
+    fn from(weather_type: &SavedWeatherType) -> Self {
+        match weather_type {
+            SavedWeatherType::Sunny => WeatherType::Sunny,
+            SavedWeatherType::Rainy => WeatherType::Rainy,
+            SavedWeatherType::Foggy => WeatherType::Foggy,
+            SavedWeatherType::TropicalMonsoon => WeatherType::TropicalMonsoon,
+            SavedWeatherType::TrentinoSnow => WeatherType::TrentinoSnow,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generated() -> SavedWorld {
+        let mut generator = WorldGenerator::new(12, 3, 10.0, 0.5, 5.0, 2.0).with_seed(4);
+        SavedWorld::generate(&mut generator)
+    }
+
+    fn assert_same(a: &SavedWorld, b: &SavedWorld) {
+        assert_eq!(a.seed, b.seed);
+        assert_eq!(a.spawn, b.spawn);
+        assert_eq!(a.max_score, b.max_score);
+        assert_eq!(a.score_table(), b.score_table());
+        for (row_a, row_b) in a.world().iter().zip(b.world()) {
+            for (tile_a, tile_b) in row_a.iter().zip(row_b) {
+                assert_eq!(tile_a.tile_type, tile_b.tile_type);
+                assert_eq!(tile_a.content, tile_b.content);
+                assert_eq!(tile_a.elevation, tile_b.elevation);
+            }
+        }
+    }
+
+    #[test]
+    fn both_formats_round_trip() {
+        let saved = generated();
+        for format in [SaveFormat::Json, SaveFormat::Binary] {
+            let decoded = SavedWorld::decode(&saved.encode(format).unwrap()).unwrap();
+            assert_same(&saved, &decoded);
+        }
+    }
+
+    #[test]
+    fn binary_starts_with_magic_and_version() {
+        let bytes = generated().encode(SaveFormat::Binary).unwrap();
+        assert_eq!(&bytes[..4], BINARY_MAGIC);
+        assert_eq!(&bytes[4..8], &FORMAT_VERSION.to_le_bytes());
+    }
+
+    #[test]
+    fn bad_magic_is_refused() {
+        let mut bytes = generated().encode(SaveFormat::Binary).unwrap();
+        bytes[0] = b'X';
+        assert!(SavedWorld::decode(&bytes).is_err());
+        assert!(SavedWorld::decode(&bytes[..6]).is_err());
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let mut saved = generated();
+        saved.version = FORMAT_VERSION + 1;
+        for format in [SaveFormat::Json, SaveFormat::Binary] {
+            assert!(matches!(
+                SavedWorld::decode(&saved.encode(format).unwrap()),
+                Err(SaveError::UnsupportedVersion(version)) if version == FORMAT_VERSION + 1
+            ));
+        }
+    }
+
+    #[test]
+    fn spawn_outside_of_the_world_is_refused() {
+        let mut saved = generated();
+        saved.spawn = (100, 0);
+        assert!(matches!(
+            SavedWorld::decode(&saved.encode(SaveFormat::Json).unwrap()),
+            Err(SaveError::InvalidData(_))
+        ));
+    }
+
+    #[test]
+    fn refused_weather_is_saved_as_the_default() {
+        let weather = WeatherConfig {
+            forecast: Vec::new(),
+            ..Default::default()
+        };
+        let mut generator = WorldGenerator::new(12, 3, 10.0, 0.5, 5.0, 2.0)
+            .with_seed(4)
+            .with_weather(weather);
+        let saved = SavedWorld::generate(&mut generator);
+        assert!(SavedWorld::decode(&saved.encode(SaveFormat::Json).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn loader_refuses_an_invalid_weather() {
+        let mut saved = generated();
+        saved.weather.forecast.clear();
+        assert!(matches!(
+            WorldLoader::new(saved),
+            Err(SaveError::InvalidData(_))
+        ));
+    }
+
+    #[test]
+    fn loader_replays_the_saved_world() {
+        let saved = generated();
+        let (world, spawn, _, max_score, _) = WorldLoader::new(saved.clone()).unwrap().gen();
+        assert_eq!(spawn, saved.spawn);
+        assert_eq!(max_score, saved.max_score);
+        assert_eq!(world.len(), saved.tiles.len());
+    }
+}
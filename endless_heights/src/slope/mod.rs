@@ -0,0 +1,152 @@
+use crate::height::HeightMap;
+use crate::hydrology::neighbours;
+
+/// Amount of relaxation passes before the remaining steep steps are cut down.
+const RELAX_PASSES: usize = 100;
+
+/// What the slope limiting stage found and did.
+/// - steepest_before: Highest elevation difference between neighbouring tiles before the stage.
+/// - passes: Amount of relaxation passes run.
+/// - modified: Amount of tiles whose elevation changed.
+/// - total_change: Sum over every tile of how much its elevation changed.
+/// - max_change: Largest change of the elevation of a single tile.
+#[derive(Clone, Debug, Default)]
+pub struct SlopeReport {
+    pub steepest_before: usize,
+    pub passes: usize,
+    pub modified: usize,
+    pub total_change: usize,
+    pub max_change: usize,
+}
+
+/// Returns the highest elevation difference between neighbouring tiles of the height map.
+pub fn steepest(height_map: &HeightMap) -> usize {
+    let (rows, cols) = (height_map.rows(), height_map.cols());
+    let mut steepest = 0;
+    for i in 0..rows {
+        for j in 0..cols {
+            for (ni, nj) in neighbours(i, j, rows, cols) {
+                steepest = steepest.max(
+                    height_map
+                        .elevation(i, j)
+                        .abs_diff(height_map.elevation(ni, nj)),
+                );
+            }
+        }
+    }
+    steepest
+}
+
+/// Lowers the slopes of the height map until no two neighbouring tiles differ by more than max_difference.
+/// Each pass moves half of the excess of every steep step from its upper tile to its lower one, so that material is kept and peaks sink as valleys rise.
+/// Steps still too steep after RELAX_PASSES passes are cut down by lowering their upper tile.
+pub fn limit(height_map: &mut HeightMap, max_difference: usize) -> SlopeReport {
+    let (rows, cols) = (height_map.rows(), height_map.cols());
+    let original = height_map.elevations().to_vec();
+    let steepest_before = steepest(height_map);
+
+    let mut passes = 0;
+    let mut steep = steepest_before > max_difference;
+    while steep && passes < RELAX_PASSES {
+        steep = false;
+        passes += 1;
+        for i in 0..rows {
+            for j in 0..cols {
+                // Every pair of neighbours once
+                for (ni, nj) in [(i + 1, j), (i, j + 1)] {
+                    if ni >= rows || nj >= cols {
+                        continue;
+                    }
+                    let (here, there) = (height_map.elevation(i, j), height_map.elevation(ni, nj));
+                    let difference = here.abs_diff(there);
+                    if difference <= max_difference {
+                        continue;
+                    }
+                    steep = true;
+                    let excess = difference - max_difference;
+                    let (high, low) = if here > there {
+                        ((i, j), (ni, nj))
+                    } else {
+                        ((ni, nj), (i, j))
+                    };
+                    let (high_elevation, low_elevation) = (here.max(there), here.min(there));
+                    height_map.set_elevation(high.0, high.1, high_elevation - excess.div_ceil(2));
+                    height_map.set_elevation(low.0, low.1, low_elevation + excess / 2);
+                }
+            }
+        }
+    }
+
+    if steep {
+        // Sweeps in both directions until no tile is more than max_difference above a neighbour
+        let mut lowered = true;
+        while lowered {
+            lowered = false;
+            let forward = (0..rows).flat_map(|i| (0..cols).map(move |j| (i, j)));
+            let backward = (0..rows)
+                .rev()
+                .flat_map(|i| (0..cols).rev().map(move |j| (i, j)));
+            for (i, j) in forward.chain(backward) {
+                let bound = neighbours(i, j, rows, cols)
+                    .map(|(ni, nj)| height_map.elevation(ni, nj) + max_difference)
+                    .min()
+                    .unwrap_or(usize::MAX);
+                if height_map.elevation(i, j) > bound {
+                    height_map.set_elevation(i, j, bound);
+                    lowered = true;
+                }
+            }
+        }
+    }
+
+    let changes = original
+        .iter()
+        .zip(height_map.elevations())
+        .map(|(before, after)| before.abs_diff(*after));
+    let mut report = SlopeReport {
+        steepest_before,
+        passes,
+        ..Default::default()
+    };
+    for change in changes {
+        if change > 0 {
+            report.modified += 1;
+            report.total_change += change;
+            report.max_change = report.max_change.max(change);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::height::create_height_map;
+    use crate::utils::Dimension;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn limit_bounds_every_step() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let dimension = Dimension {
+            width: 40,
+            height: 30,
+        };
+        let mut height_map = create_height_map(&mut rng, &dimension, 20, 60.0, 0.1, 2.0, 6.0);
+        let steepest_before = steepest(&height_map);
+        assert!(steepest_before > 3);
+        let report = limit(&mut height_map, 3);
+        assert!(steepest(&height_map) <= 3);
+        assert_eq!(report.steepest_before, steepest_before);
+        assert!(report.modified > 0 && report.max_change > 0);
+    }
+
+    #[test]
+    fn gentle_maps_are_left_untouched() {
+        let mut height_map = HeightMap::from_elevations(&[vec![0, 1, 2], vec![1, 2, 3]]);
+        let report = limit(&mut height_map, 1);
+        assert_eq!(height_map.elevations(), &[0, 1, 2, 1, 2, 3]);
+        assert_eq!((report.passes, report.modified), (0, 0));
+    }
+}
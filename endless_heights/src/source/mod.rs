@@ -0,0 +1,145 @@
+/// Fractal gradient noise sources.
+pub mod noise;
+
+/// A source of elevation values.
+/// `x` runs along the rows of the map and `y` along the columns, matching the gaussians of the height module.
+pub trait HeightSource: Send + Sync {
+    /// Returns the elevation at coordinate x, y.
+    fn height_at(&self, x: f32, y: f32) -> f32;
+
+    /// Writes the elevations of row x, from column 0 onwards, into out.
+    /// Sources can override it to share work between the tiles of a row.
+    fn fill_row(&self, x: f32, out: &mut [f32]) {
+        for (j, elevation) in out.iter_mut().enumerate() {
+            *elevation = self.height_at(x, j as f32);
+        }
+    }
+}
+
+/// How a layer is combined with the elevation below it.
+pub enum BlendOp {
+    /// Sums the layer to the base.
+    Add,
+    /// Keeps the highest of base and layer.
+    Max,
+    /// Multiplies the base by the layer.
+    Multiply,
+    /// Interpolates from the base to the layer by the value of the mask, clamped between 0 and 1.
+    Mask(Box<dyn HeightSource>),
+}
+
+impl BlendOp {
+    /// Combines the base and layer values found at coordinate x, y.
+    pub fn apply(&self, base: f32, layer: f32, x: f32, y: f32) -> f32 {
+        match self {
+            BlendOp::Add => base + layer,
+            BlendOp::Max => f32::max(base, layer),
+            BlendOp::Multiply => base * layer,
+            BlendOp::Mask(mask) => {
+                let t = mask.height_at(x, y).clamp(0.0, 1.0);
+                base + (layer - base) * t
+            }
+        }
+    }
+}
+
+/// Two sources combined with a BlendOp.
+pub struct Blend {
+    pub base: Box<dyn HeightSource>,
+    pub layer: Box<dyn HeightSource>,
+    pub op: BlendOp,
+}
+
+impl HeightSource for Blend {
+    fn height_at(&self, x: f32, y: f32) -> f32 {
+        self.op
+            .apply(self.base.height_at(x, y), self.layer.height_at(x, y), x, y)
+    }
+}
+
+/// A base source with layers blended on top of it in order.
+pub struct Stack<'a> {
+    pub base: &'a dyn HeightSource,
+    pub layers: &'a [(BlendOp, Box<dyn HeightSource>)],
+}
+
+impl HeightSource for Stack<'_> {
+    fn height_at(&self, x: f32, y: f32) -> f32 {
+        self.layers
+            .iter()
+            .fold(self.base.height_at(x, y), |elevation, (op, layer)| {
+                op.apply(elevation, layer.height_at(x, y), x, y)
+            })
+    }
+
+    fn fill_row(&self, x: f32, out: &mut [f32]) {
+        self.base.fill_row(x, out);
+        if self.layers.is_empty() {
+            return;
+        }
+        let mut layer_values = vec![0.0; out.len()];
+        for (op, layer) in self.layers {
+            layer.fill_row(x, &mut layer_values);
+            for (j, (elevation, value)) in out.iter_mut().zip(&layer_values).enumerate() {
+                *elevation = op.apply(*elevation, *value, x, j as f32);
+            }
+        }
+    }
+}
+
+/// A source returning the same elevation everywhere. Mostly useful as a mask or a multiplier.
+pub struct Constant(pub f32);
+
+impl HeightSource for Constant {
+    fn height_at(&self, _x: f32, _y: f32) -> f32 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rises by one along the rows and by ten along the columns.
+    struct Ramp;
+
+    impl HeightSource for Ramp {
+        fn height_at(&self, x: f32, y: f32) -> f32 {
+            x + 10.0 * y
+        }
+    }
+
+    #[test]
+    fn blend_ops_combine_base_and_layer() {
+        assert_eq!(BlendOp::Add.apply(2.0, 3.0, 0.0, 0.0), 5.0);
+        assert_eq!(BlendOp::Max.apply(2.0, 3.0, 0.0, 0.0), 3.0);
+        assert_eq!(BlendOp::Multiply.apply(2.0, 3.0, 0.0, 0.0), 6.0);
+        let mask = BlendOp::Mask(Box::new(Ramp));
+        assert_eq!(mask.apply(2.0, 4.0, 0.5, 0.0), 3.0);
+        // The mask is clamped between 0 and 1
+        assert_eq!(mask.apply(2.0, 4.0, 0.0, 5.0), 4.0);
+    }
+
+    #[test]
+    fn stack_rows_match_its_tiles() {
+        let layers: Vec<(BlendOp, Box<dyn HeightSource>)> = vec![
+            (BlendOp::Multiply, Box::new(Constant(0.5))),
+            (BlendOp::Max, Box::new(Constant(12.0))),
+            (
+                BlendOp::Mask(Box::new(Constant(0.25))),
+                Box::new(Constant(0.0)),
+            ),
+        ];
+        let stack = Stack {
+            base: &Ramp,
+            layers: &layers,
+        };
+        let mut row = vec![0.0; 6];
+        stack.fill_row(3.0, &mut row);
+        for (j, value) in row.iter().enumerate() {
+            assert_eq!(*value, stack.height_at(3.0, j as f32));
+        }
+        assert_eq!(row[0], 9.0);
+        assert_eq!(row[5], 0.75 * 26.5);
+    }
+}
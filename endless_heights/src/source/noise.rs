@@ -0,0 +1,203 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use super::HeightSource;
+
+/// Unit gradients used by the noise lattice.
+const GRADIENTS: [(f32, f32); 8] = [
+    (1.0, 0.0),
+    (-1.0, 0.0),
+    (0.0, 1.0),
+    (0.0, -1.0),
+    (
+        std::f32::consts::FRAC_1_SQRT_2,
+        std::f32::consts::FRAC_1_SQRT_2,
+    ),
+    (
+        -std::f32::consts::FRAC_1_SQRT_2,
+        std::f32::consts::FRAC_1_SQRT_2,
+    ),
+    (
+        std::f32::consts::FRAC_1_SQRT_2,
+        -std::f32::consts::FRAC_1_SQRT_2,
+    ),
+    (
+        -std::f32::consts::FRAC_1_SQRT_2,
+        -std::f32::consts::FRAC_1_SQRT_2,
+    ),
+];
+
+/// Two dimensional Perlin gradient noise.
+/// The permutation table is shuffled from the seed, so the same seed always gives the same noise.
+pub struct Perlin {
+    permutation: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Perlin {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut permutation = (0..256).collect::<Vec<usize>>();
+        permutation.shuffle(&mut rng);
+        permutation.extend_from_within(..);
+        Perlin { permutation }
+    }
+
+    /// Returns the noise at coordinate x, y, roughly between -1 and 1.
+    pub fn noise(&self, x: f32, y: f32) -> f32 {
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let (dx, dy) = (x - x0, y - y0);
+        let (xi, yi) = ((x0 as i64 & 255) as usize, (y0 as i64 & 255) as usize);
+
+        let corner = |ci: usize, cj: usize, ox: f32, oy: f32| {
+            let hash = self.permutation[self.permutation[xi + ci] + yi + cj];
+            let (gx, gy) = GRADIENTS[hash % GRADIENTS.len()];
+            gx * (dx - ox) + gy * (dy - oy)
+        };
+        let n00 = corner(0, 0, 0.0, 0.0);
+        let n10 = corner(1, 0, 1.0, 0.0);
+        let n01 = corner(0, 1, 0.0, 1.0);
+        let n11 = corner(1, 1, 1.0, 1.0);
+
+        let (u, v) = (fade(dx), fade(dy));
+        let nx0 = lerp(n00, n10, u);
+        let nx1 = lerp(n01, n11, u);
+        // Scaling brings the theoretical range of 2D Perlin noise back to about [-1, 1]
+        lerp(nx0, nx1, v) * std::f32::consts::SQRT_2
+    }
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// How strongly the signal of an octave of ridged noise weighs the next octave.
+const RIDGE_WEIGHTING: f32 = 2.0;
+
+/// How the octaves of a FractalNoise are summed.
+#[derive(Clone, Copy, Debug)]
+pub enum FractalKind {
+    /// Fractal Brownian motion: soft rolling hills.
+    Fbm,
+    /// Ridged multifractal: sharp crests where the noise crosses zero.
+    /// Each octave is weighted by the signal of the previous one, so that details gather on the crests and valleys stay smooth.
+    Ridged,
+}
+
+/// Fractal noise made of several octaves of Perlin noise.
+/// ## Parameters
+/// - kind: How octaves are summed.
+/// - amplitude: The highest elevation the noise can reach. Values lie between 0 and amplitude.
+/// - frequency: Frequency of the first octave, in cycles per tile.
+/// - octaves: Amount of noise layers summed together. Defaults to 5.
+/// - lacunarity: Frequency multiplier between successive octaves. Defaults to 2.
+/// - gain: Amplitude multiplier between successive octaves. Defaults to 0.5.
+pub struct FractalNoise {
+    perlin: Perlin,
+    pub kind: FractalKind,
+    pub amplitude: f32,
+    pub frequency: f32,
+    pub octaves: usize,
+    pub lacunarity: f32,
+    pub gain: f32,
+}
+
+impl FractalNoise {
+    pub fn new(seed: u64, kind: FractalKind, amplitude: f32, frequency: f32) -> FractalNoise {
+        FractalNoise {
+            perlin: Perlin::new(seed),
+            kind,
+            amplitude,
+            frequency,
+            octaves: 5,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+}
+
+impl HeightSource for FractalNoise {
+    fn height_at(&self, x: f32, y: f32) -> f32 {
+        let mut frequency = self.frequency;
+        let mut weight = 1.0;
+        let mut total_weight = 0.0;
+        let mut sum = 0.0;
+        // Weight of the next ridged octave, from the signal of the previous one
+        let mut ridge_weight = 1.0;
+        for _ in 0..self.octaves {
+            let n = self.perlin.noise(x * frequency, y * frequency);
+            sum += weight
+                * match self.kind {
+                    FractalKind::Fbm => (n + 1.0) / 2.0,
+                    FractalKind::Ridged => {
+                        let signal = f32::powi(1.0 - n.abs(), 2) * ridge_weight;
+                        ridge_weight = (signal * RIDGE_WEIGHTING).clamp(0.0, 1.0);
+                        signal
+                    }
+                };
+            total_weight += weight;
+            weight *= self.gain;
+            frequency *= self.lacunarity;
+        }
+        if total_weight == 0.0 {
+            return 0.0;
+        }
+        self.amplitude * (sum / total_weight).clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn perlin_is_zero_on_the_lattice_and_follows_the_seed() {
+        let perlin = Perlin::new(4);
+        for (x, y) in [(0.0, 0.0), (3.0, -7.0), (255.0, 256.0)] {
+            assert_eq!(perlin.noise(x, y), 0.0);
+        }
+        let samples = |perlin: &Perlin| {
+            (0..50)
+                .map(|k| perlin.noise(0.37 * k as f32, 0.61 * k as f32))
+                .collect::<Vec<f32>>()
+        };
+        assert_eq!(samples(&perlin), samples(&Perlin::new(4)));
+        assert_ne!(samples(&perlin), samples(&Perlin::new(5)));
+    }
+
+    #[test]
+    fn fractal_noise_stays_within_its_amplitude() {
+        for kind in [FractalKind::Fbm, FractalKind::Ridged] {
+            let noise = FractalNoise::new(9, kind, 40.0, 0.05);
+            for i in 0..30 {
+                for j in 0..30 {
+                    let value = noise.height_at(i as f32, j as f32);
+                    assert!((0.0..=40.0).contains(&value), "{:?}: {}", kind, value);
+                }
+            }
+        }
+        let mut flat = FractalNoise::new(9, FractalKind::Fbm, 40.0, 0.05);
+        flat.octaves = 0;
+        assert_eq!(flat.height_at(3.5, 2.5), 0.0);
+    }
+
+    #[test]
+    fn ridged_octaves_are_weighted_by_the_previous_signal() {
+        let mut noise = FractalNoise::new(9, FractalKind::Ridged, 30.0, 0.07);
+        noise.octaves = 2;
+        let perlin = Perlin::new(9);
+        for k in 0..40 {
+            let (x, y) = (1.3 * k as f32, 0.7 * k as f32);
+            let first = f32::powi(1.0 - perlin.noise(0.07 * x, 0.07 * y).abs(), 2);
+            let weight = (2.0 * first).clamp(0.0, 1.0);
+            let second = f32::powi(1.0 - perlin.noise(0.14 * x, 0.14 * y).abs(), 2) * weight;
+            let expected = 30.0 * (first + 0.5 * second) / 1.5;
+            assert!((noise.height_at(x, y) - expected).abs() < 1e-4);
+        }
+    }
+}
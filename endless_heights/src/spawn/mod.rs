@@ -0,0 +1,277 @@
+use std::collections::VecDeque;
+use std::mem::discriminant;
+
+use rand::rngs::StdRng;
+use rand::Rng;
+use robotics_lib::world::tile::{Content, TileType};
+
+use crate::hydrology::neighbours;
+use crate::World;
+
+/// How the spawn point of the robot is chosen.
+/// Every strategy returns a walkable tile whose neighbours are all walkable.
+#[derive(Clone, Debug, Default)]
+pub enum SpawnStrategy {
+    /// The lowest tile of the map.
+    LowestValley,
+    /// A random tile.
+    RandomWalkable,
+    /// A random tile of the largest connected walkable region.
+    #[default]
+    LargestRegion,
+    /// The given tile, or the closest valid one if it is not walkable.
+    Fixed((usize, usize)),
+    /// The closest valid tile to any tile holding the given content. Quantity is ignored.
+    /// Falls back to `LargestRegion` when the content is nowhere on the map.
+    NearContent(Content),
+}
+
+/// Chooses the spawn point following the given strategy.
+/// If no tile of the world is a valid spawn point, the chosen tile and its neighbours are turned into Grass.
+pub fn choose(rng: &mut StdRng, world: &mut World, strategy: &SpawnStrategy) -> (usize, usize) {
+    let rows = world.len();
+    let cols = world.first().map_or(0, |row| row.len());
+    let candidates = (0..rows)
+        .flat_map(|i| (0..cols).map(move |j| (i, j)))
+        .filter(|&(i, j)| is_valid(world, i, j))
+        .collect::<Vec<(usize, usize)>>();
+
+    let spawn = match strategy {
+        SpawnStrategy::LowestValley => candidates
+            .iter()
+            .min_by_key(|&&(i, j)| world[i][j].elevation)
+            .copied(),
+        SpawnStrategy::RandomWalkable => random_of(rng, &candidates),
+        SpawnStrategy::LargestRegion => largest_region(rng, world),
+        SpawnStrategy::Fixed(position) => closest_valid(world, &[*position]),
+        SpawnStrategy::NearContent(content) => {
+            let sources = (0..rows)
+                .flat_map(|i| (0..cols).map(move |j| (i, j)))
+                .filter(|&(i, j)| discriminant(&world[i][j].content) == discriminant(content))
+                .collect::<Vec<(usize, usize)>>();
+            if sources.is_empty() {
+                largest_region(rng, world)
+            } else {
+                closest_valid(world, &sources)
+            }
+        }
+    };
+
+    spawn.unwrap_or_else(|| {
+        let fallback = match strategy {
+            SpawnStrategy::Fixed((i, j)) => (
+                usize::min(*i, rows.saturating_sub(1)),
+                usize::min(*j, cols.saturating_sub(1)),
+            ),
+            _ => (0, 0),
+        };
+        make_walkable(world, fallback);
+        fallback
+    })
+}
+
+/// Returns the connected groups of walkable tiles, in row-major order of their first tile.
+pub fn walkable_regions(world: &World) -> Vec<Vec<(usize, usize)>> {
+    let rows = world.len();
+    let cols = world.first().map_or(0, |row| row.len());
+    let mut seen = vec![vec![false; cols]; rows];
+    let mut regions = Vec::new();
+    for i in 0..rows {
+        for j in 0..cols {
+            if seen[i][j] || !is_walkable(world, i, j) {
+                continue;
+            }
+            seen[i][j] = true;
+            let mut region = vec![(i, j)];
+            let mut queue = VecDeque::from([(i, j)]);
+            while let Some((ci, cj)) = queue.pop_front() {
+                for (ni, nj) in neighbours(ci, cj, rows, cols) {
+                    if !seen[ni][nj] && is_walkable(world, ni, nj) {
+                        seen[ni][nj] = true;
+                        region.push((ni, nj));
+                        queue.push_back((ni, nj));
+                    }
+                }
+            }
+            regions.push(region);
+        }
+    }
+    regions
+}
+
+pub(crate) fn is_walkable(world: &World, i: usize, j: usize) -> bool {
+    world[i][j].tile_type.properties().walk()
+}
+
+/// A tile is a valid spawn point when it and all its neighbours are walkable.
+fn is_valid(world: &World, i: usize, j: usize) -> bool {
+    let rows = world.len();
+    let cols = world[0].len();
+    is_walkable(world, i, j)
+        && neighbours(i, j, rows, cols).all(|(ni, nj)| is_walkable(world, ni, nj))
+}
+
+fn random_of(rng: &mut StdRng, tiles: &[(usize, usize)]) -> Option<(usize, usize)> {
+    if tiles.is_empty() {
+        None
+    } else {
+        Some(tiles[rng.gen_range(0..tiles.len())])
+    }
+}
+
+/// Picks a random valid tile of the largest walkable region.
+fn largest_region(rng: &mut StdRng, world: &World) -> Option<(usize, usize)> {
+    let mut regions = walkable_regions(world);
+    regions.sort_by_key(|region| std::cmp::Reverse(region.len()));
+    regions.into_iter().find_map(|region| {
+        let valid = region
+            .into_iter()
+            .filter(|&(i, j)| is_valid(world, i, j))
+            .collect::<Vec<(usize, usize)>>();
+        random_of(rng, &valid)
+    })
+}
+
+/// Returns the valid tile closest to any of the sources, measured in steps on the grid.
+fn closest_valid(world: &World, sources: &[(usize, usize)]) -> Option<(usize, usize)> {
+    let rows = world.len();
+    let cols = world.first().map_or(0, |row| row.len());
+    let mut seen = vec![vec![false; cols]; rows];
+    let mut queue = VecDeque::new();
+    for &(i, j) in sources {
+        if i < rows && j < cols && !seen[i][j] {
+            seen[i][j] = true;
+            queue.push_back((i, j));
+        }
+    }
+    while let Some((i, j)) = queue.pop_front() {
+        if is_valid(world, i, j) {
+            return Some((i, j));
+        }
+        for (ni, nj) in neighbours(i, j, rows, cols) {
+            if !seen[ni][nj] {
+                seen[ni][nj] = true;
+                queue.push_back((ni, nj));
+            }
+        }
+    }
+    None
+}
+
+/// Turns a tile and its neighbours into empty Grass, so that the tile becomes a valid spawn point.
+fn make_walkable(world: &mut World, (i, j): (usize, usize)) {
+    let rows = world.len();
+    let cols = world.first().map_or(0, |row| row.len());
+    if i >= rows || j >= cols {
+        return;
+    }
+    for (ti, tj) in neighbours(i, j, rows, cols).chain(std::iter::once((i, j))) {
+        if !is_walkable(world, ti, tj) {
+            world[ti][tj].tile_type = TileType::Grass;
+            world[ti][tj].content = Content::None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use robotics_lib::world::tile::Tile;
+
+    /// Deep water on the left half, grass on the right half, a coin on the water side and a pit on the grass side.
+    fn coast() -> World {
+        let mut world = (0..8)
+            .map(|i| {
+                (0..10)
+                    .map(|j| Tile {
+                        tile_type: if j < 5 {
+                            TileType::DeepWater
+                        } else {
+                            TileType::Grass
+                        },
+                        content: Content::None,
+                        elevation: 10 * i + j,
+                    })
+                    .collect()
+            })
+            .collect::<World>();
+        world[4][1].content = Content::Coin(3);
+        world[2][8].elevation = 0;
+        world
+    }
+
+    #[test]
+    fn every_strategy_spawns_on_a_valid_tile() {
+        for strategy in [
+            SpawnStrategy::LowestValley,
+            SpawnStrategy::RandomWalkable,
+            SpawnStrategy::LargestRegion,
+            SpawnStrategy::Fixed((3, 2)),
+            SpawnStrategy::NearContent(Content::Coin(0)),
+            SpawnStrategy::NearContent(Content::Fish(0)),
+        ] {
+            let mut world = coast();
+            let mut rng = StdRng::seed_from_u64(1);
+            let (i, j) = choose(&mut rng, &mut world, &strategy);
+            assert!(is_valid(&world, i, j), "{:?} at ({}, {})", strategy, i, j);
+            assert!(world == coast());
+        }
+    }
+
+    #[test]
+    fn strategies_pick_the_expected_tile() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut world = coast();
+        assert_eq!(
+            choose(&mut rng, &mut world, &SpawnStrategy::LowestValley),
+            (2, 8)
+        );
+        // The closest tile whose neighbours are all walkable is two columns away from the water
+        assert_eq!(
+            choose(&mut rng, &mut world, &SpawnStrategy::Fixed((3, 2))),
+            (3, 6)
+        );
+        assert_eq!(
+            choose(
+                &mut rng,
+                &mut world,
+                &SpawnStrategy::NearContent(Content::Coin(0))
+            ),
+            (4, 6)
+        );
+    }
+
+    #[test]
+    fn spawn_is_cleared_when_nothing_is_walkable() {
+        let mut world = vec![
+            vec![
+                Tile {
+                    tile_type: TileType::Lava,
+                    content: Content::Fire,
+                    elevation: 0,
+                };
+                4
+            ];
+            3
+        ];
+        let mut rng = StdRng::seed_from_u64(1);
+        let spawn = choose(&mut rng, &mut world, &SpawnStrategy::Fixed((1, 9)));
+        assert_eq!(spawn, (1, 3));
+        assert!(is_valid(&world, 1, 3));
+        assert_eq!(world[1][3].content, Content::None);
+    }
+
+    #[test]
+    fn walkable_regions_are_split_by_water() {
+        let mut world = coast();
+        for row in world.iter_mut() {
+            row[7].tile_type = TileType::DeepWater;
+        }
+        let regions = walkable_regions(&world);
+        assert_eq!(
+            regions.iter().map(Vec::len).collect::<Vec<usize>>(),
+            vec![16, 16]
+        );
+    }
+}
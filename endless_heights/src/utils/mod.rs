@@ -1,8 +1,10 @@
+#[derive(Clone, Debug)]
 pub struct Dimension {
     pub width: usize,
     pub height: usize,
 }
 
+#[derive(Clone, Debug)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
@@ -0,0 +1,133 @@
+use robotics_lib::world::environmental_conditions::{EnvironmentalConditions, WeatherType};
+
+/// Environmental conditions of the generated world.
+/// - forecast: The cycle of weather types the world goes through.
+/// - time_progression_minutes: Minutes that pass at every tick.
+/// - starting_hour: Hour of the day the world starts at.
+#[derive(Clone, Debug)]
+pub struct WeatherConfig {
+    pub forecast: Vec<WeatherType>,
+    pub time_progression_minutes: u8,
+    pub starting_hour: u8,
+}
+
+impl Default for WeatherConfig {
+    fn default() -> Self {
+        Self {
+            forecast: vec![WeatherType::Sunny],
+            time_progression_minutes: 1,
+            starting_hour: 1,
+        }
+    }
+}
+
+impl WeatherConfig {
+    /// Builds the environmental conditions from the configuration.
+    /// Fails with the message of robotics_lib when the configuration is not accepted.
+    pub fn conditions(&self) -> Result<EnvironmentalConditions, String> {
+        EnvironmentalConditions::new(
+            &self.forecast,
+            self.time_progression_minutes,
+            self.starting_hour,
+        )
+    }
+}
+
+/// Returns the environmental conditions of the default weather configuration.
+pub(crate) fn default_conditions() -> EnvironmentalConditions {
+    WeatherConfig::default()
+        .conditions()
+        .expect("the default weather configuration is accepted by robotics_lib")
+}
+
+/// Preset weather cycles for common climates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Climate {
+    /// Mostly sunny, with some rain.
+    Temperate,
+    /// Snow and fog, starting in the early morning.
+    Alpine,
+    /// Rain and monsoons with sunny spells.
+    Tropical,
+    /// Sunny all day long.
+    Arid,
+}
+
+impl Climate {
+    /// Returns the weather configuration of the climate.
+    pub fn weather(&self) -> WeatherConfig {
+        match self {
+            Climate::Temperate => WeatherConfig {
+                forecast: vec![
+                    WeatherType::Sunny,
+                    WeatherType::Sunny,
+                    WeatherType::Rainy,
+                    WeatherType::Foggy,
+                ],
+                time_progression_minutes: 10,
+                starting_hour: 8,
+            },
+            Climate::Alpine => WeatherConfig {
+                forecast: vec![
+                    WeatherType::TrentinoSnow,
+                    WeatherType::Foggy,
+                    WeatherType::TrentinoSnow,
+                    WeatherType::Sunny,
+                ],
+                time_progression_minutes: 10,
+                starting_hour: 6,
+            },
+            Climate::Tropical => WeatherConfig {
+                forecast: vec![
+                    WeatherType::Rainy,
+                    WeatherType::TropicalMonsoon,
+                    WeatherType::Sunny,
+                    WeatherType::Rainy,
+                ],
+                time_progression_minutes: 10,
+                starting_hour: 10,
+            },
+            Climate::Arid => WeatherConfig {
+                forecast: vec![WeatherType::Sunny],
+                time_progression_minutes: 10,
+                starting_hour: 12,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WorldGenerator;
+    use robotics_lib::world::world_generator::Generator;
+
+    #[test]
+    fn presets_are_accepted() {
+        for climate in [
+            Climate::Temperate,
+            Climate::Alpine,
+            Climate::Tropical,
+            Climate::Arid,
+        ] {
+            assert!(climate.weather().conditions().is_ok(), "{:?}", climate);
+        }
+    }
+
+    #[test]
+    fn refused_weather_falls_back_to_the_default() {
+        let weather = WeatherConfig {
+            forecast: Vec::new(),
+            ..Default::default()
+        };
+        assert!(weather.conditions().is_err());
+        let mut generator = WorldGenerator::new(10, 2, 10.0, 0.5, 5.0, 2.0)
+            .with_seed(1)
+            .with_weather(weather);
+        let default = WeatherConfig::default();
+        assert_eq!(generator.weather().forecast.len(), default.forecast.len());
+        assert_eq!(generator.weather().starting_hour, default.starting_hour);
+        // Does not panic
+        generator.gen();
+    }
+}